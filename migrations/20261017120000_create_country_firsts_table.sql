@@ -0,0 +1,9 @@
+-- Last known country #1 of every tracked beatmap
+CREATE TABLE IF NOT EXISTS country_firsts (
+    beatmap_id INTEGER NOT NULL,
+    mode TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    score INTEGER NOT NULL,
+    PRIMARY KEY (beatmap_id, mode)
+);
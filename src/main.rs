@@ -1,14 +1,17 @@
 mod modules;
 
+use modules::beatmap_ref::BeatmapRef;
+use modules::channel_context;
+use modules::commands::{self, CommandReply, LeaderboardOptions, TopPlaysOptions};
+use modules::country_tracker;
+use modules::data_source::{self, data_source};
 use modules::database;
-use modules::generate_lb::generate_leaderboard;
-use modules::osu_api;
+use modules::slash_commands;
 
 use dotenvy::dotenv;
 use std::env;
-use std::sync::Mutex;
 
-use serenity::all::{CreateAttachment, CreateMessage, Ready};
+use serenity::all::{Command, CreateMessage, Interaction, Ready};
 use serenity::async_trait;
 use serenity::model::channel::Message;
 use serenity::prelude::*;
@@ -17,284 +20,354 @@ struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn ready(&self, ctx: Context, ready: Ready) {
         println!("🤖 {} is connected and running!", ready.user.name);
+
+        country_tracker::start_country_tracker(ctx.http.clone());
+
+        if let Err(e) =
+            Command::set_global_commands(&ctx.http, slash_commands::create_commands()).await
+        {
+            println!("Error registering slash commands: {:?}", e);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => slash_commands::handle_command(&ctx, &command).await,
+            Interaction::Autocomplete(autocomplete) => {
+                slash_commands::handle_autocomplete(&ctx, &autocomplete).await
+            }
+            Interaction::Component(component) => {
+                slash_commands::handle_component(&ctx, &component).await
+            }
+            Interaction::Modal(modal) => slash_commands::handle_modal(&ctx, &modal).await,
+            _ => {}
+        }
     }
 
     async fn message(&self, ctx: Context, msg: Message) {
+        if let Some(beatmap) = channel_context::find_beatmap_in_message(&msg) {
+            channel_context::remember_beatmap(msg.channel_id.get(), beatmap);
+        }
+
         if !msg.content.starts_with("!") {
             return;
         }
 
+        let msg_args: Vec<&str> = msg.content.split_whitespace().collect();
+        let positional_args = get_positional_args(&msg_args);
+
         if msg.content.starts_with("!cs") {
-            osu_api::handle_legacy_score_only(&msg.content).await;
-            let msg_args: Vec<&str> = msg.content.split_whitespace().collect();
+            let beatmap =
+                get_beatmap_arg(&msg, &positional_args, "Usage: !cs <beatmap_id or link>");
+
+            let reply = match (
+                beatmap,
+                parse_leaderboard_options(&msg_args, msg.author.id.get()),
+            ) {
+                (Ok(beatmap), Ok(options)) => {
+                    commands::handle_beatmap_lb(data_source(), beatmap, &options).await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            send_reply(&ctx, &msg, reply).await;
+        }
 
-            let beatmap_id = match msg_args.last() {
-                Some(id) => id,
-                None => {
-                    if let Err(e) = msg.reply(&ctx.http, "Usage: !cs <beatmap_id>").await {
-                        println!("Error sending message: {:?}", e);
-                    }
-                    return;
+        if msg.content.starts_with("!ss") {
+            let beatmap =
+                get_beatmap_arg(&msg, &positional_args, "Usage: !ss <beatmap_id or link>");
+
+            let reply = match (
+                beatmap,
+                parse_leaderboard_options(&msg_args, msg.author.id.get()),
+            ) {
+                (Ok(beatmap), Ok(options)) => {
+                    commands::handle_beatmap_server_lb(
+                        data_source(),
+                        &ctx.http,
+                        msg.guild_id,
+                        beatmap,
+                        &options,
+                    )
+                    .await
                 }
+                (Err(e), _) | (_, Err(e)) => Err(e),
             };
+            send_reply(&ctx, &msg, reply).await;
+        }
 
-            handle_generate_country_lb(&ctx, &msg, beatmap_id).await;
+        // Exact match, !rsc starts with !rs too
+        if msg_args[0] == "!rs" {
+            let user_arg = positional_args.last().copied().unwrap_or("");
+
+            let reply = match parse_leaderboard_options(&msg_args, msg.author.id.get()) {
+                Ok(options) => {
+                    commands::handle_recent_score(
+                        data_source(),
+                        msg.author.id.get(),
+                        user_arg,
+                        &options,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            send_reply(&ctx, &msg, reply).await;
         }
 
         if msg.content.starts_with("!rsc") {
-            osu_api::handle_legacy_score_only(&msg.content).await;
-            let msg_args: Vec<&str> = msg.content.split_whitespace().collect();
-
-            let user_arg = match msg_args.last() {
-                Some(last) => {
-                    let last_index = msg_args.len() - 1;
-                    if last_index > 0 && msg_args[last_index - 1] == "-m" {
-                        ""
-                    } else if last.starts_with('-') || *last == "!rsc" {
-                        ""
-                    } else {
-                        last
-                    }
+            let user_arg = positional_args.last().copied().unwrap_or("");
+
+            let reply = match parse_leaderboard_options(&msg_args, msg.author.id.get()) {
+                Ok(options) => {
+                    commands::handle_recent_country_lb(
+                        data_source(),
+                        msg.author.id.get(),
+                        user_arg,
+                        &options,
+                    )
+                    .await
                 }
-                None => "",
+                Err(e) => Err(e),
             };
+            send_reply(&ctx, &msg, reply).await;
+        }
 
-            let user = match user_arg.is_empty() {
-                true => match database::get_user_by_id(msg.author.id.get() as i64).await {
-                    Ok(u) => u.osu_id.to_string(),
-                    Err(e) => {
-                        let error_msg = match e {
-                            database::UserError::UserNotFound => "Failed to get user. Did you connect your osu! account with `!connect {osu_id}`?",
-                            database::UserError::DatabaseError(e) => &e.to_string(),
-                        };
-
-                        if let Err(e) = msg.reply(&ctx.http, error_msg).await {
-                            println!("Error sending message: {:?}", e);
-                        }
-                        return;
-                    }
-                },
+        if msg.content.starts_with("!compare") {
+            let (beatmap_args, user_arg) = split_compare_args(&positional_args);
+            let beatmap = get_beatmap_arg(
+                &msg,
+                beatmap_args,
+                "Usage: !compare [beatmap_id or link] [user]",
+            );
+
+            let reply = match (
+                beatmap,
+                parse_leaderboard_options(&msg_args, msg.author.id.get()),
+            ) {
+                (Ok(beatmap), Ok(options)) => {
+                    commands::handle_compare(
+                        data_source(),
+                        msg.author.id.get(),
+                        user_arg.unwrap_or(""),
+                        beatmap,
+                        &options,
+                    )
+                    .await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            send_reply(&ctx, &msg, reply).await;
+        }
 
-                false => user_arg.to_string(),
+        if msg.content.starts_with("!top") {
+            let user_arg = positional_args.last().copied().unwrap_or("");
+
+            let reply = match parse_top_options(&msg_args) {
+                Ok(options) => {
+                    commands::handle_top_plays(
+                        data_source(),
+                        msg.author.id.get(),
+                        user_arg,
+                        &options,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
             };
+            send_reply(&ctx, &msg, reply).await;
+        }
 
-            let recent = match osu_api::get_user_recent(&user).await {
-                Ok(r) => r,
-                Err(e) => {
-                    let error_msg = match e {
-                        osu_api::OsuApiError::NotFound(e) => &e.to_string(),
-                        _ => "An unknown error occured. Please try again later.",
-                    };
-                    if let Err(e) = msg.reply(&ctx.http, error_msg).await {
-                        println!("Error sending message: {:?}", e);
-                    }
-                    return;
+        if msg.content.starts_with("!profile") {
+            let user_arg = positional_args.last().copied().unwrap_or("");
+
+            let reply = match commands::parse_mode_arg(get_flag_value(&msg_args, "-mode")) {
+                Ok(mode) => {
+                    commands::handle_profile(data_source(), msg.author.id.get(), user_arg, mode)
+                        .await
                 }
+                Err(e) => Err(e),
             };
+            send_reply(&ctx, &msg, reply).await;
+        }
+
+        if msg.content.starts_with("!map") {
+            let beatmap =
+                get_beatmap_arg(&msg, &positional_args, "Usage: !map <beatmap_id or link>");
+            let mode = commands::parse_mode_arg(get_flag_value(&msg_args, "-mode"));
+            let mods = get_flag_value(&msg_args, "-m").unwrap_or("");
 
-            handle_generate_country_lb(&ctx, &msg, &recent.beatmap.id.to_string()).await;
+            let reply = match (beatmap, mode) {
+                (Ok(beatmap), Ok(mode)) => {
+                    commands::handle_beatmap_info(data_source(), beatmap, mods, mode).await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            send_reply(&ctx, &msg, reply).await;
         }
 
         if msg.content.starts_with("!connect") {
-            let msg_args: Vec<&str> = msg.content.split_whitespace().collect();
-
             let osu_id = match msg_args.last() {
                 Some(id) => match id.parse::<i64>() {
                     Ok(parsed_id) => parsed_id,
                     Err(_) => {
-                        if let Err(e) = msg
-                            .reply(&ctx.http, "Invalid osu! ID. Please provide a valid number.")
-                            .await
-                        {
-                            println!("Error sending message: {:?}", e);
-                        }
+                        let error_msg = "Invalid osu! ID. Please provide a valid number.";
+                        send_reply(&ctx, &msg, Err(error_msg.to_string())).await;
                         return;
                     }
                 },
                 None => {
-                    if let Err(e) = msg.reply(&ctx.http, "Usage: !connect <osu_id>").await {
-                        println!("Error sending message: {:?}", e);
-                    }
+                    send_reply(&ctx, &msg, Err("Usage: !connect <osu_id>".to_string())).await;
                     return;
                 }
             };
 
-            match database::insert_user(msg.author.id.get() as i64, &msg.author.name, osu_id).await
-            {
-                Ok(_) => {
-                    if let Err(e) = msg
-                        .reply(&ctx.http, "Successfully connected your osu! account")
-                        .await
-                    {
-                        println!("Error sending message: {:?}", e);
-                    }
-                }
-                Err(e) => {
-                    let error_msg = match e {
-                        sqlx::Error::Database(db_error)
-                            if db_error.code() == Some("1555".into()) =>
-                        {
-                            "This Discord account is already connected to an osu! account"
-                        }
-                        _ => "An unknown error occured. Please try again later.",
-                    };
-
-                    if let Err(e) = msg.reply(&ctx.http, error_msg).await {
-                        println!("Error sending message: {:?}", e);
-                    }
-                    return;
-                }
-            }
+            let reply =
+                commands::handle_connect(msg.author.id.get(), &msg.author.name, osu_id).await;
+            send_reply(&ctx, &msg, reply).await;
         }
     }
 }
 
-async fn handle_generate_country_lb(ctx: &Context, msg: &Message, beatmap_id: &str) {
-    let mut scores = match osu_api::fetch_country_scores(&beatmap_id).await {
-        Ok(s) => s,
-        Err(e) => {
-            let error_msg = match e {
-                osu_api::OsuApiError::RequestFailed(_e) => {
-                    "Failed to fetch scores. Check if the beatmap ID is correct."
-                }
-                osu_api::OsuApiError::NotFound(e) => &e.to_string(),
-                _ => "An unknown error occured. Please try again later.",
-            };
-            if let Err(e) = msg.reply(&ctx.http, error_msg).await {
-                println!("Error sending message: {:?}", e);
-            }
-            return;
+// Flags that consume the following argument as their value
+const VALUE_FLAGS: [&str; 9] = [
+    "-m", "-mode", "-type", "-p", "-player", "-rank", "-from", "-to", "-sort",
+];
+
+fn get_positional_args<'a>(msg_args: &[&'a str]) -> Vec<&'a str> {
+    let mut positional_args = Vec::new();
+    let mut skip_next = true; // the command itself
+
+    for arg in msg_args {
+        if skip_next {
+            skip_next = false;
+        } else if VALUE_FLAGS.contains(arg) {
+            skip_next = true;
+        } else if !arg.starts_with('-') {
+            positional_args.push(*arg);
         }
-    };
+    }
 
-    let msg_tokens = msg.content.split(" ").collect::<Vec<&str>>();
-    let mut mods = String::new();
+    positional_args
+}
 
-    for (i, token) in msg_tokens.iter().enumerate() {
-        if *token == "-m" && i + 1 < msg_tokens.len() {
-            mods = msg_tokens[i + 1].to_string().to_uppercase();
-            break;
-        }
+// Without an explicit beatmap, use the replied-to message or the channel's last beatmap
+fn get_beatmap_arg(
+    msg: &Message,
+    positional_args: &[&str],
+    usage: &str,
+) -> Result<BeatmapRef, String> {
+    match positional_args.last() {
+        Some(beatmap) => commands::parse_beatmap_arg(beatmap),
+        None => msg
+            .referenced_message
+            .as_deref()
+            .and_then(channel_context::find_beatmap_in_message)
+            .or_else(|| channel_context::get_last_beatmap(msg.channel_id.get()))
+            .ok_or_else(|| usage.to_string()),
     }
+}
 
-    if !mods.is_empty() {
-        let valid_mods = vec!["HD", "HR", "DT", "NC", "FL", "EZ", "HT", "SO", "NF"];
-        let filter_mods = get_mods_without_cl(&mods);
-
-        let invalid_mods: Vec<&String> = filter_mods
-            .iter()
-            .filter(|chunk| !valid_mods.contains(&chunk.as_str()))
-            .collect();
-
-        if !invalid_mods.is_empty() {
-            if let Err(e) = msg
-                .reply(&ctx.http, format!("Invalid mods {:?}", invalid_mods))
-                .await
-            {
-                println!("Error sending message: {:?}", e);
-            }
-            return;
-        }
-
-        scores.retain(|score| {
-            let score_mods = score
-                .mods
-                .iter()
-                .map(|m| m.acronym.clone())
-                .collect::<Vec<String>>()
-                .join("");
-
-            let mods_without_cl = get_mods_without_cl(&score_mods);
-
-            mods_without_cl == filter_mods
-        });
-
-        if scores.is_empty() {
-            if let Err(e) = msg
-                .reply(&ctx.http, "No scores found with the specified mods")
-                .await
-            {
-                println!("Error sending message: {:?}", e);
-            }
-            return;
-        }
+// A lone argument is the user like in the other commands unless it's a beatmap link,
+// a beatmap ID only counts when a user follows it
+fn split_compare_args<'a, 'b>(positional_args: &'b [&'a str]) -> (&'b [&'a str], Option<&'a str>) {
+    match positional_args {
+        [_, user, ..] => (&positional_args[..1], Some(*user)),
+        [arg] if is_beatmap_link(arg) => (positional_args, None),
+        [user] => (&[], Some(*user)),
+        [] => (&[], None),
     }
+}
 
-    scores.truncate(7);
+fn is_beatmap_link(arg: &str) -> bool {
+    arg.parse::<i64>().is_err() && commands::parse_beatmap_arg(arg).is_ok()
+}
 
-    let beatmap_info = match osu_api::fetch_beatmap_info(&beatmap_id).await {
-        Ok(b) => b,
-        Err(e) => {
-            let error_msg = match e {
-                osu_api::OsuApiError::RequestFailed(_e) => {
-                    "Failed to fetch beatmap info. Check if the beatmap ID is correct."
-                }
-                _ => "An unknown error occured. Please try again later.",
-            };
-            if let Err(e) = msg.reply(&ctx.http, error_msg).await {
-                println!("Error sending message: {:?}", e);
-            }
-            return;
-        }
-    };
+fn parse_leaderboard_options(
+    msg_args: &[&str],
+    requester: u64,
+) -> Result<LeaderboardOptions, String> {
+    let mut options = LeaderboardOptions::new(
+        get_flag_value(msg_args, "-m").unwrap_or(""),
+        get_flag_value(msg_args, "-mode"),
+        msg_args.contains(&"-l"),
+        get_flag_value(msg_args, "-type"),
+    )?;
+
+    options.page = parse_page_flag(msg_args)?;
+    options.player = get_flag_value(msg_args, "-player").map(|p| p.to_string());
+    options.requester = Some(requester);
+    options.refresh = msg_args.contains(&"--refresh");
+
+    Ok(options)
+}
 
-    let avatars = match osu_api::get_avatars_bytes_array(&scores).await {
-        Ok(a) => a,
-        Err(e) => {
-            let error_msg = match e {
-                _ => "An unknown error occured. Please try again later.",
-            };
-            if let Err(e) = msg.reply(&ctx.http, error_msg).await {
-                println!("Error sending message: {:?}", e);
+fn parse_top_options(msg_args: &[&str]) -> Result<TopPlaysOptions, String> {
+    let mut options = TopPlaysOptions::new(
+        get_flag_value(msg_args, "-m").unwrap_or(""),
+        get_flag_value(msg_args, "-mode"),
+        get_flag_value(msg_args, "-rank"),
+        get_flag_value(msg_args, "-from"),
+        get_flag_value(msg_args, "-to"),
+        get_flag_value(msg_args, "-sort"),
+    )?;
+
+    options.page = parse_page_flag(msg_args)?;
+
+    Ok(options)
+}
+
+fn parse_page_flag(msg_args: &[&str]) -> Result<usize, String> {
+    match get_flag_value(msg_args, "-p") {
+        Some(page) => page
+            .parse::<usize>()
+            .ok()
+            .filter(|p| *p > 0)
+            .ok_or_else(|| format!("Invalid page {}", page)),
+        None => Ok(1),
+    }
+}
+
+fn get_flag_value<'a>(msg_args: &[&'a str], flag: &str) -> Option<&'a str> {
+    msg_args
+        .iter()
+        .position(|arg| *arg == flag)
+        .and_then(|i| msg_args.get(i + 1))
+        .copied()
+}
+
+async fn send_reply(ctx: &Context, msg: &Message, reply: Result<CommandReply, String>) {
+    let msg_builder = match reply {
+        Ok(CommandReply {
+            content,
+            attachment,
+            components,
+        }) => {
+            let mut msg_builder = CreateMessage::new().content(content).components(components);
+            if let Some(attachment) = attachment {
+                msg_builder = msg_builder.add_file(attachment);
             }
-            return;
+            msg_builder
         }
+        Err(e) => CreateMessage::new().content(e),
     };
 
-    let table = generate_leaderboard(scores, avatars, &beatmap_info);
-
-    let msg_builder = CreateMessage::new()
-        .content(format!(
-            "[**{} - {} [{}]**](<https://osu.ppy.sh/beatmapsets/{}#osu/{}>)\n",
-            beatmap_info.artist,
-            beatmap_info.title,
-            beatmap_info.version,
-            beatmap_info.beatmapset_id,
-            beatmap_info.beatmap_id
-        ))
-        .add_file(CreateAttachment::bytes(table, "lb.png"))
-        .reference_message(&*msg);
-
-    if let Err(e) = msg.channel_id.send_message(&ctx.http, msg_builder).await {
+    if let Err(e) = msg
+        .channel_id
+        .send_message(&ctx.http, msg_builder.reference_message(msg))
+        .await
+    {
         println!("Error sending message: {:?}", e);
     }
 }
 
-fn get_mods_without_cl(mods: &str) -> std::collections::HashSet<String> {
-    mods.chars()
-        .collect::<Vec<char>>()
-        .chunks(2)
-        .map(|c| c.iter().collect::<String>())
-        .filter(|s| s != "CL")
-        .collect()
-}
-
 #[tokio::main]
 async fn main() {
-    osu_api::LEGACY_SCORE_ONLY.get_or_init(|| Mutex::new(true));
     dotenv().ok();
+    data_source::initialize_data_source();
     let dc_token = env::var("BOT_TOKEN").expect("Missing Discord bot token");
 
-    match osu_api::set_legacy_score_only(true).await {
-        Ok(_) => {}
-        Err(e) => {
-            println!("{}", e);
-            return;
-        }
-    }
-
     if let Err(e) = database::initialize_db().await {
         println!("Failed to initialize database: {}", e);
         return;
@@ -311,3 +384,22 @@ async fn main() {
         println!("Client error: {why:?}");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_takes_a_lone_argument_as_the_user() {
+        assert_eq!(split_compare_args(&["2"]), (&[][..], Some("2")));
+        assert_eq!(split_compare_args(&["peppy"]), (&[][..], Some("peppy")));
+        assert_eq!(split_compare_args(&[]), (&[][..], None));
+    }
+
+    #[test]
+    fn compare_takes_a_beatmap_from_a_link_or_before_the_user() {
+        let link = "https://osu.ppy.sh/b/75";
+        assert_eq!(split_compare_args(&[link]), (&[link][..], None));
+        assert_eq!(split_compare_args(&["75", "2"]), (&["75"][..], Some("2")));
+    }
+}
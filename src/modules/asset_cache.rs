@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use serenity::all::json;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAX_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+const MAX_DISK_BYTES: u64 = 512 * 1024 * 1024;
+// Checking the size of the whole directory after every download would be wasteful
+const PRUNE_EVERY_STORES: usize = 50;
+
+#[derive(Clone, Copy)]
+pub enum AssetKind {
+    Avatar,
+    Cover,
+    BeatmapFile,
+    // Files of ranked and loved beatmaps, which never change
+    FinalBeatmapFile,
+}
+
+impl AssetKind {
+    fn dir(&self) -> &'static str {
+        match self {
+            Self::Avatar => "avatars",
+            Self::Cover => "covers",
+            Self::BeatmapFile | Self::FinalBeatmapFile => "osu",
+        }
+    }
+
+    // How long a cached copy is used before asking the server again
+    fn ttl(&self) -> Duration {
+        match self {
+            Self::Avatar => Duration::from_secs(12 * 60 * 60),
+            Self::Cover => Duration::from_secs(24 * 60 * 60),
+            Self::BeatmapFile => Duration::from_secs(10 * 60),
+            Self::FinalBeatmapFile => Duration::from_secs(365 * 24 * 60 * 60),
+        }
+    }
+}
+
+pub enum Fetched {
+    Modified {
+        bytes: Vec<u8>,
+        etag: Option<String>,
+    },
+    // Answer to a request with the cached ETag, the cached copy is still current
+    NotModified,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct AssetMeta {
+    etag: Option<String>,
+    // Unix seconds of the last download or revalidation
+    fetched_at: u64,
+}
+
+impl AssetMeta {
+    fn now(etag: Option<String>) -> Self {
+        Self {
+            etag,
+            fetched_at: unix_now(),
+        }
+    }
+
+    fn age(&self) -> Duration {
+        Duration::from_secs(unix_now().saturating_sub(self.fetched_at))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+struct MemoryAsset {
+    bytes: Vec<u8>,
+    meta: AssetMeta,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+struct MemoryCache {
+    assets: HashMap<PathBuf, MemoryAsset>,
+    size: usize,
+}
+
+impl MemoryCache {
+    fn get(&mut self, path: &Path) -> Option<(Vec<u8>, AssetMeta)> {
+        let asset = self.assets.get_mut(path)?;
+        asset.last_used = Instant::now();
+        Some((asset.bytes.clone(), asset.meta.clone()))
+    }
+
+    fn insert(&mut self, path: PathBuf, bytes: Vec<u8>, meta: AssetMeta) {
+        if let Some(old) = self.assets.remove(&path) {
+            self.size -= old.bytes.len();
+        }
+
+        // Least recently used first
+        while self.size + bytes.len() > MAX_MEMORY_BYTES && !self.assets.is_empty() {
+            let oldest = self
+                .assets
+                .iter()
+                .min_by_key(|(_, a)| a.last_used)
+                .map(|(path, _)| path.clone());
+            if let Some(asset) = oldest.and_then(|path| self.assets.remove(&path)) {
+                self.size -= asset.bytes.len();
+            }
+        }
+
+        self.size += bytes.len();
+        self.assets.insert(
+            path,
+            MemoryAsset {
+                bytes,
+                meta,
+                last_used: Instant::now(),
+            },
+        );
+    }
+}
+
+// Assets are kept in memory and, when a directory is given, on disk across restarts
+pub struct AssetCache {
+    dir: Option<PathBuf>,
+    memory: Mutex<MemoryCache>,
+    stores: AtomicUsize,
+}
+
+impl AssetCache {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            memory: Mutex::new(MemoryCache::default()),
+            stores: AtomicUsize::new(0),
+        }
+    }
+
+    // Only cached on disk when ASSET_CACHE_DIR is set
+    pub fn from_env() -> Self {
+        Self::new(env::var("ASSET_CACHE_DIR").ok().map(PathBuf::from))
+    }
+
+    // Serves `key` from memory or disk while it's fresh, otherwise calls `fetch` with the cached
+    // ETag. A stale copy is still served when `fetch` fails, e.g. while osu!'s CDN is down.
+    pub async fn get_or_fetch<F, Fut, E>(
+        &self,
+        kind: AssetKind,
+        key: &str,
+        fetch: F,
+    ) -> Result<Vec<u8>, E>
+    where
+        F: FnOnce(Option<String>) -> Fut,
+        Fut: Future<Output = Result<Fetched, E>>,
+        E: Display,
+    {
+        let path = asset_path(kind, key);
+
+        let in_memory = self.memory.lock().unwrap().get(&path);
+        let cached = match in_memory {
+            Some(asset) => Some(asset),
+            None => {
+                let asset = match &self.dir {
+                    Some(dir) => read_from_disk(&dir.join(&path)).await,
+                    None => None,
+                };
+                if let Some((bytes, meta)) = &asset {
+                    self.memory
+                        .lock()
+                        .unwrap()
+                        .insert(path.clone(), bytes.clone(), meta.clone());
+                }
+                asset
+            }
+        };
+
+        if let Some((bytes, meta)) = &cached {
+            if meta.age() < kind.ttl() {
+                return Ok(bytes.clone());
+            }
+        }
+
+        let etag = cached.as_ref().and_then(|(_, meta)| meta.etag.clone());
+        match (fetch(etag).await, cached) {
+            (Ok(Fetched::Modified { bytes, etag }), _) => {
+                self.store(path, bytes.clone(), AssetMeta::now(etag)).await;
+                Ok(bytes)
+            }
+            (Ok(Fetched::NotModified), Some((bytes, meta))) => {
+                self.store(path, bytes.clone(), AssetMeta::now(meta.etag))
+                    .await;
+                Ok(bytes)
+            }
+            // Only possible if the server ignores that no ETag was sent
+            (Ok(Fetched::NotModified), None) => Ok(Vec::new()),
+            (Err(e), Some((bytes, _))) => {
+                println!("Serving stale {} after failing to refresh it: {}", key, e);
+                Ok(bytes)
+            }
+            (Err(e), None) => Err(e),
+        }
+    }
+
+    async fn store(&self, path: PathBuf, bytes: Vec<u8>, meta: AssetMeta) {
+        if let Some(dir) = &self.dir {
+            let disk_path = dir.join(&path);
+            if let Err(e) = write_to_disk(&disk_path, &bytes, &meta).await {
+                println!("Failed to cache {}: {}", disk_path.display(), e);
+            }
+
+            if self
+                .stores
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(PRUNE_EVERY_STORES)
+            {
+                if let Err(e) = prune_disk(dir).await {
+                    println!("Failed to prune the asset cache: {}", e);
+                }
+            }
+        }
+
+        self.memory.lock().unwrap().insert(path, bytes, meta);
+    }
+}
+
+// Keys like URLs become file names, e.g. avatars/https___a.ppy.sh_2_1519081077.png
+fn asset_path(kind: AssetKind, key: &str) -> PathBuf {
+    let file_name: String = key
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect();
+
+    Path::new(kind.dir()).join(file_name)
+}
+
+fn meta_path(path: &Path) -> PathBuf {
+    let mut meta_path = path.as_os_str().to_owned();
+    meta_path.push(".meta");
+    PathBuf::from(meta_path)
+}
+
+// Files cached before the metadata existed count as downloaded when they were written
+async fn read_from_disk(path: &Path) -> Option<(Vec<u8>, AssetMeta)> {
+    let bytes = tokio::fs::read(path).await.ok()?;
+
+    let meta = match tokio::fs::read(meta_path(path)).await {
+        Ok(meta) => json::from_slice::<AssetMeta>(&meta).ok(),
+        Err(_) => None,
+    };
+    let meta = match meta {
+        Some(meta) => meta,
+        None => {
+            let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+            AssetMeta {
+                etag: None,
+                fetched_at: modified
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            }
+        }
+    };
+
+    Some((bytes, meta))
+}
+
+async fn write_to_disk(path: &Path, bytes: &[u8], meta: &AssetMeta) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, bytes).await?;
+
+    let meta = json::to_vec(meta).map_err(std::io::Error::other)?;
+    tokio::fs::write(meta_path(path), meta).await
+}
+
+// Deletes the least recently downloaded assets until the cache fits into MAX_DISK_BYTES
+async fn prune_disk(cache_dir: &Path) -> std::io::Result<()> {
+    let mut files = Vec::new();
+    let mut total = 0;
+
+    for kind in [AssetKind::Avatar, AssetKind::Cover, AssetKind::BeatmapFile] {
+        let dir = cache_dir.join(kind.dir());
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "meta") {
+                continue;
+            }
+
+            let metadata = entry.metadata().await?;
+            total += metadata.len();
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+
+    files.sort_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in files {
+        if total <= MAX_DISK_BYTES {
+            break;
+        }
+
+        tokio::fs::remove_file(&path).await?;
+        let _ = tokio::fs::remove_file(meta_path(&path)).await;
+        total -= len;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn fetch_ok(bytes: &[u8]) -> Result<Fetched, String> {
+        Ok(Fetched::Modified {
+            bytes: bytes.to_vec(),
+            etag: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn serves_fresh_assets_from_memory_without_a_directory() {
+        let cache = AssetCache::new(None);
+
+        let first = cache
+            .get_or_fetch(AssetKind::Avatar, "a", |_| fetch_ok(b"first"))
+            .await;
+        let second = cache
+            .get_or_fetch(AssetKind::Avatar, "a", |_| fetch_ok(b"second"))
+            .await;
+
+        assert_eq!(first.unwrap(), b"first");
+        assert_eq!(second.unwrap(), b"first");
+    }
+
+    #[tokio::test]
+    async fn keeps_assets_on_disk_in_the_given_directory() {
+        let dir = env::temp_dir().join(format!("asset-cache-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        AssetCache::new(Some(dir.clone()))
+            .get_or_fetch(AssetKind::BeatmapFile, "75.osu", |_| fetch_ok(b"osu"))
+            .await
+            .unwrap();
+
+        // A new cache, like after a restart, finds the file without fetching it
+        let restarted = AssetCache::new(Some(dir.clone()))
+            .get_or_fetch(AssetKind::BeatmapFile, "75.osu", |_| async {
+                Err::<Fetched, _>("not fetched".to_string())
+            })
+            .await;
+
+        assert_eq!(restarted.unwrap(), b"osu");
+        assert!(dir.join("osu").join("75.osu").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,267 @@
+use super::osu_api::GameMode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeatmapRef {
+    Beatmap { id: i64, mode: Option<GameMode> },
+    Beatmapset { id: i64, mode: Option<GameMode> },
+}
+
+const OSU_HOSTS: [&str; 3] = ["osu.ppy.sh", "old.ppy.sh", "lazer.ppy.sh"];
+
+// Accepts a raw beatmap ID or any of the common osu! beatmap URL forms:
+//   https://osu.ppy.sh/beatmapsets/123#osu/456
+//   https://osu.ppy.sh/beatmapsets/123/discussion/456/general
+//   https://osu.ppy.sh/beatmapsets/123
+//   https://osu.ppy.sh/beatmaps/456?mode=taiko
+//   https://osu.ppy.sh/b/456?m=1, https://osu.ppy.sh/s/123
+//   https://osu.ppy.sh/p/beatmap?b=456, https://osu.ppy.sh/p/beatmap?s=123
+pub fn parse_beatmap_ref(input: &str) -> Option<BeatmapRef> {
+    let input = input.trim().trim_start_matches('<').trim_end_matches('>');
+
+    if let Some(id) = parse_id(input) {
+        return Some(BeatmapRef::Beatmap { id, mode: None });
+    }
+
+    let without_scheme = input
+        .strip_prefix("https://")
+        .or_else(|| input.strip_prefix("http://"))
+        .unwrap_or(input);
+
+    let (host, rest) = without_scheme.split_once('/')?;
+    if !OSU_HOSTS.contains(&host.to_lowercase().as_str()) {
+        return None;
+    }
+
+    let (rest, fragment) = match rest.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (rest, None),
+    };
+    let (path, query) = match rest.split_once(['?', '&']) {
+        Some((path, query)) => (path, query),
+        None => (rest, ""),
+    };
+
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let query_mode = get_query_param(query, "m")
+        .or_else(|| get_query_param(query, "mode"))
+        .and_then(GameMode::from_arg);
+
+    match segments.as_slice() {
+        ["beatmapsets", set_id, rest @ ..] => {
+            let set_id = parse_id(set_id)?;
+
+            // #osu/456, #456 or #osu
+            if let Some(fragment) = fragment {
+                let (fragment_mode, fragment_id) = match fragment.split_once('/') {
+                    Some((mode, id)) => (GameMode::from_arg(mode), id),
+                    None if parse_id(fragment).is_some() => (None, fragment),
+                    None => (GameMode::from_arg(fragment), ""),
+                };
+                if let Some(id) = parse_id(fragment_id) {
+                    return Some(BeatmapRef::Beatmap {
+                        id,
+                        mode: fragment_mode,
+                    });
+                }
+                if fragment_mode.is_some() {
+                    return Some(BeatmapRef::Beatmapset {
+                        id: set_id,
+                        mode: fragment_mode,
+                    });
+                }
+            }
+
+            // /beatmapsets/123/discussion/456/general
+            if let ["discussion", beatmap_id, ..] = rest {
+                if let Some(id) = parse_id(beatmap_id) {
+                    return Some(BeatmapRef::Beatmap { id, mode: None });
+                }
+            }
+
+            Some(BeatmapRef::Beatmapset {
+                id: set_id,
+                mode: query_mode,
+            })
+        }
+        ["beatmaps", id, ..] | ["b", id, ..] => Some(BeatmapRef::Beatmap {
+            id: parse_id(id)?,
+            mode: query_mode,
+        }),
+        ["s", id, ..] => Some(BeatmapRef::Beatmapset {
+            id: parse_id(id)?,
+            mode: query_mode,
+        }),
+        ["p", "beatmap", ..] => {
+            if let Some(id) = get_query_param(query, "b").and_then(parse_id) {
+                Some(BeatmapRef::Beatmap {
+                    id,
+                    mode: query_mode,
+                })
+            } else {
+                Some(BeatmapRef::Beatmapset {
+                    id: get_query_param(query, "s").and_then(parse_id)?,
+                    mode: query_mode,
+                })
+            }
+        }
+        _ => None,
+    }
+}
+
+fn parse_id(id: &str) -> Option<i64> {
+    id.parse::<i64>().ok().filter(|id| *id > 0)
+}
+
+fn get_query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split(['&', '?'])
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+// Finds the first osu! beatmap link in free text, e.g. a message or embed description
+pub fn find_beatmap_ref(text: &str) -> Option<BeatmapRef> {
+    let mut link_starts: Vec<usize> = OSU_HOSTS
+        .iter()
+        .flat_map(|host| text.match_indices(host).map(|(i, _)| i))
+        .collect();
+    link_starts.sort_unstable();
+
+    link_starts.into_iter().find_map(|start| {
+        let link = &text[start..];
+        let end = link
+            .find(|c: char| c.is_whitespace() || matches!(c, ')' | '>' | ']' | '"' | '\''))
+            .unwrap_or(link.len());
+        parse_beatmap_ref(&link[..end])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beatmap(id: i64, mode: Option<GameMode>) -> Option<BeatmapRef> {
+        Some(BeatmapRef::Beatmap { id, mode })
+    }
+
+    fn beatmapset(id: i64, mode: Option<GameMode>) -> Option<BeatmapRef> {
+        Some(BeatmapRef::Beatmapset { id, mode })
+    }
+
+    #[test]
+    fn raw_ids_are_beatmaps() {
+        assert_eq!(parse_beatmap_ref("75"), beatmap(75, None));
+        assert_eq!(parse_beatmap_ref(" 75 "), beatmap(75, None));
+    }
+
+    #[test]
+    fn new_site_difficulty_links() {
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmapsets/1#osu/75"),
+            beatmap(75, Some(GameMode::Osu))
+        );
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmapsets/1#taiko/75"),
+            beatmap(75, Some(GameMode::Taiko))
+        );
+        assert_eq!(
+            parse_beatmap_ref("<https://osu.ppy.sh/beatmapsets/1#mania/75>"),
+            beatmap(75, Some(GameMode::Mania))
+        );
+    }
+
+    #[test]
+    fn beatmap_links() {
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmaps/75"),
+            beatmap(75, None)
+        );
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmaps/75?mode=fruits"),
+            beatmap(75, Some(GameMode::Fruits))
+        );
+        assert_eq!(
+            parse_beatmap_ref("osu.ppy.sh/beatmaps/75"),
+            beatmap(75, None)
+        );
+    }
+
+    #[test]
+    fn old_site_links() {
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/b/75"),
+            beatmap(75, None)
+        );
+        assert_eq!(
+            parse_beatmap_ref("http://old.ppy.sh/b/75?m=1"),
+            beatmap(75, Some(GameMode::Taiko))
+        );
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/s/1"),
+            beatmapset(1, None)
+        );
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/p/beatmap?b=75"),
+            beatmap(75, None)
+        );
+    }
+
+    // These need a difficulty to be picked
+    #[test]
+    fn set_links() {
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmapsets/1"),
+            beatmapset(1, None)
+        );
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmapsets/1#taiko"),
+            beatmapset(1, Some(GameMode::Taiko))
+        );
+    }
+
+    #[test]
+    fn discussion_links_point_at_their_difficulty() {
+        assert_eq!(
+            parse_beatmap_ref("https://osu.ppy.sh/beatmapsets/1/discussion/75/general"),
+            beatmap(75, None)
+        );
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for input in [
+            "",
+            "abc",
+            "-75",
+            "0",
+            "75abc",
+            "https://osu.ppy.sh/",
+            "https://osu.ppy.sh/beatmaps/abc",
+            "https://osu.ppy.sh/beatmapsets/",
+            "https://osu.ppy.sh/users/2",
+        ] {
+            assert_eq!(parse_beatmap_ref(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_other_hosts() {
+        for input in [
+            "https://example.com/beatmaps/75",
+            "https://osu.ppy.sh.example.com/b/75",
+            "https://example.com/osu.ppy.sh/b/75",
+        ] {
+            assert_eq!(parse_beatmap_ref(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn finds_links_in_text() {
+        assert_eq!(
+            find_beatmap_ref("Try [this map](https://osu.ppy.sh/b/75) instead"),
+            beatmap(75, None)
+        );
+        assert_eq!(find_beatmap_ref("No link here"), None);
+    }
+}
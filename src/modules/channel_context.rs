@@ -0,0 +1,38 @@
+use super::beatmap_ref::{find_beatmap_ref, BeatmapRef};
+
+use once_cell::sync::Lazy;
+use serenity::all::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// Last beatmap mentioned or rendered in each channel, keyed by channel ID
+static LAST_BEATMAPS: Lazy<Mutex<HashMap<u64, BeatmapRef>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn remember_beatmap(channel_id: u64, beatmap: BeatmapRef) {
+    LAST_BEATMAPS.lock().unwrap().insert(channel_id, beatmap);
+}
+
+pub fn get_last_beatmap(channel_id: u64) -> Option<BeatmapRef> {
+    LAST_BEATMAPS.lock().unwrap().get(&channel_id).copied()
+}
+
+// Looks for a beatmap link in the message content and in embeds posted by other bots
+pub fn find_beatmap_in_message(msg: &Message) -> Option<BeatmapRef> {
+    find_beatmap_ref(&msg.content).or_else(|| {
+        msg.embeds.iter().find_map(|embed| {
+            let author_url = embed.author.as_ref().and_then(|a| a.url.as_deref());
+            let fields = embed.fields.iter().map(|f| f.value.as_str());
+
+            [
+                embed.url.as_deref(),
+                author_url,
+                embed.description.as_deref(),
+            ]
+            .into_iter()
+            .flatten()
+            .chain(fields)
+            .find_map(find_beatmap_ref)
+        })
+    })
+}
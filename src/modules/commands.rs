@@ -0,0 +1,1697 @@
+use super::beatmap_ref::{parse_beatmap_ref, BeatmapRef};
+use super::data_source::OsuDataSource;
+use super::database;
+use super::generate_card::{
+    generate_beatmap_card, generate_profile_card, generate_recent_card, generate_top_plays,
+    BeatmapCard, RecentCard, TopPlayRow,
+};
+use super::generate_lb::{generate_leaderboard, LeaderboardPage, LeaderboardRow};
+use super::leaderboard_cache::{self, LeaderboardKey};
+use super::leaderboard_pages::{self, LeaderboardSession, PAGE_SIZE};
+use super::mods::{Mods, ModsFilter};
+use super::osu_api::{
+    Beatmap, GameMode, OsuApiError, RecentScore, Score, ScoreBoard, ScoringMode, User,
+};
+use super::performance::{AdjustedStats, PerformanceCalculator};
+
+use serenity::all::{
+    ButtonStyle, CreateActionRow, CreateAttachment, CreateButton, GuildId, Http, HttpError, UserId,
+};
+use serenity::futures::stream::{self, StreamExt};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+// Offered while typing mods, any other known mod can still be typed in full
+const SUGGESTED_MODS: [&str; 13] = [
+    "HD", "HR", "DT", "NC", "FL", "EZ", "HT", "SO", "NF", "SD", "PF", "TD", "CL",
+];
+
+pub struct CommandReply {
+    pub content: String,
+    pub attachment: Option<CreateAttachment>,
+    pub components: Vec<CreateActionRow>,
+}
+
+impl CommandReply {
+    pub fn text(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            attachment: None,
+            components: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum LeaderboardType {
+    Global,
+    // Country and friends of the account whose session the bot uses
+    Country,
+    Friends,
+    // Any other country, filtered from the global top scores
+    CountryCode(String),
+    // osu! ids of the linked members of a Discord server
+    Server(Vec<i64>),
+    // All scores of one player, e.g. one per mod combination
+    Player(User),
+}
+
+impl LeaderboardType {
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "global" | "g" => Some(Self::Global),
+            "country" | "c" => Some(Self::Country),
+            "friends" | "friend" | "f" => Some(Self::Friends),
+            code if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) => {
+                Some(Self::CountryCode(code.to_uppercase()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            Self::Global => "Global leaderboard".to_string(),
+            Self::Country => "Country leaderboard".to_string(),
+            Self::Friends => "Friends leaderboard".to_string(),
+            Self::CountryCode(code) => format!("{} players in global top", code),
+            Self::Server(_) => "Server leaderboard".to_string(),
+            Self::Player(user) => format!("Scores of {}", user.username),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LeaderboardOptions {
+    pub mods: ModsFilter,
+    pub mode: Option<GameMode>,
+    pub scoring: ScoringMode,
+    pub board: LeaderboardType,
+    // 1-based, or the page containing `player` if set
+    pub page: usize,
+    pub player: Option<String>,
+    // Discord ID of the user who asked, whose score gets highlighted
+    pub requester: Option<u64>,
+    // Skips the leaderboard cache
+    pub refresh: bool,
+}
+
+impl LeaderboardOptions {
+    pub fn new(
+        mods: &str,
+        mode: Option<&str>,
+        lazer: bool,
+        board: Option<&str>,
+    ) -> Result<Self, String> {
+        let mode = parse_mode_arg(mode)?;
+
+        let board = match board {
+            Some(b) => LeaderboardType::from_arg(b).ok_or_else(|| {
+                format!(
+                    "Invalid leaderboard type {}. Use global, country, friends or a country code.",
+                    b
+                )
+            })?,
+            None => LeaderboardType::Country,
+        };
+
+        Ok(Self {
+            mods: ModsFilter::parse(mods)?,
+            mode,
+            scoring: ScoringMode::from_lazer_flag(lazer),
+            board,
+            page: 1,
+            player: None,
+            requester: None,
+            refresh: false,
+        })
+    }
+}
+
+pub fn parse_mode_arg(mode: Option<&str>) -> Result<Option<GameMode>, String> {
+    match mode {
+        Some(m) => GameMode::from_arg(m)
+            .map(Some)
+            .ok_or_else(|| format!("Invalid mode {}. Use osu, taiko, fruits or mania.", m)),
+        None => Ok(None),
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum TopPlaysSort {
+    Pp,
+    Accuracy,
+    Combo,
+    Date,
+}
+
+impl TopPlaysSort {
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "pp" => Some(Self::Pp),
+            "acc" | "accuracy" => Some(Self::Accuracy),
+            "combo" => Some(Self::Combo),
+            "date" | "recent" => Some(Self::Date),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pp => "pp",
+            Self::Accuracy => "accuracy",
+            Self::Combo => "combo",
+            Self::Date => "date",
+        }
+    }
+}
+
+pub struct TopPlaysOptions {
+    pub mods: ModsFilter,
+    pub mode: Option<GameMode>,
+    // SS and S also match their silver versions
+    pub rank: Option<String>,
+    // Inclusive YYYY-MM-DD dates
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub sort: TopPlaysSort,
+    pub page: usize,
+}
+
+impl TopPlaysOptions {
+    pub fn new(
+        mods: &str,
+        mode: Option<&str>,
+        rank: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        sort: Option<&str>,
+    ) -> Result<Self, String> {
+        let rank = match rank.map(|r| r.to_uppercase()) {
+            Some(r) if ["SS", "S", "A", "B", "C", "D"].contains(&r.as_str()) => Some(r),
+            Some(r) => return Err(format!("Invalid rank {}. Use SS, S, A, B, C or D.", r)),
+            None => None,
+        };
+
+        let sort = match sort {
+            Some(s) => TopPlaysSort::from_arg(s)
+                .ok_or_else(|| format!("Invalid sort {}. Use pp, acc, combo or date.", s))?,
+            None => TopPlaysSort::Pp,
+        };
+
+        let from = from.map(parse_date_arg).transpose()?;
+        let to = to.map(parse_date_arg).transpose()?;
+        if let (Some(from), Some(to)) = (&from, &to) {
+            if from > to {
+                return Err(format!(
+                    "The start date {} is after the end date {}",
+                    from, to
+                ));
+            }
+        }
+
+        Ok(Self {
+            mods: ModsFilter::parse(mods)?,
+            mode: parse_mode_arg(mode)?,
+            rank,
+            from,
+            to,
+            sort,
+            page: 1,
+        })
+    }
+}
+
+// Accepts e.g. 2024-5-1 and normalizes it to 2024-05-01 so dates compare as strings
+fn parse_date_arg(date: &str) -> Result<String, String> {
+    let invalid = || format!("Invalid date {}. Use YYYY-MM-DD.", date);
+
+    let parts: Vec<u32> = date
+        .split('-')
+        .map(|p| p.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+
+    // osu! was released in 2007, there are no scores before that
+    match parts[..] {
+        [year, month, day]
+            if (2007..=9999).contains(&year)
+                && (1..=12).contains(&month)
+                && (1..=days_in_month(year, month)).contains(&day) =>
+        {
+            Ok(format!("{:04}-{:02}-{:02}", year, month, day))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    let leap_year =
+        year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+pub async fn handle_generate_lb(
+    source: &dyn OsuDataSource,
+    beatmap_id: &str,
+    options: &LeaderboardOptions,
+) -> Result<CommandReply, String> {
+    let (session, page) = load_leaderboard(source, beatmap_id, options).await?;
+    let (session_id, session) = leaderboard_pages::store_session(session);
+    render_leaderboard_page(source, session_id, &session, page).await
+}
+
+// The filtered leaderboard and the page to show first
+async fn load_leaderboard(
+    source: &dyn OsuDataSource,
+    beatmap_id: &str,
+    options: &LeaderboardOptions,
+) -> Result<(LeaderboardSession, usize), String> {
+    let key = LeaderboardKey::new(beatmap_id, options);
+    let cached = match options.refresh {
+        true => None,
+        false => leaderboard_cache::get(&key),
+    };
+
+    let (beatmap_info, mut scores, fetched_at) = match cached {
+        Some(cached) => (
+            cached.beatmap.clone(),
+            cached.scores.clone(),
+            cached.fetched_at,
+        ),
+        None => {
+            let (beatmap_info, scores) = fetch_leaderboard(source, beatmap_id, options).await?;
+            leaderboard_cache::insert(key, beatmap_info.clone(), scores.clone());
+            (beatmap_info, scores, Instant::now())
+        }
+    };
+
+    let mode = options.mode.unwrap_or(beatmap_info.mode);
+
+    scores.retain(|score| options.mods.matches(&Mods::from_score(&score.mods)));
+
+    // Server boards show the best score of each member that's left after filtering
+    if let LeaderboardType::Server(_) = options.board {
+        let mut members = HashSet::new();
+        scores.retain(|score| members.insert(score.user.id));
+    }
+
+    if scores.is_empty() {
+        return Err("No scores found with the specified mods".to_string());
+    }
+
+    let calculator = get_calculator(source, &beatmap_info, mode).await;
+
+    let stats = calculator
+        .as_ref()
+        .map(|c| c.adjusted_stats(&options.mods.star_mods()));
+
+    // Only linked requesters can be found on the leaderboard
+    let requester_osu_id = match options.requester {
+        Some(discord_id) => database::get_user_by_id(discord_id as i64)
+            .await
+            .ok()
+            .map(|u| u.osu_id),
+        None => None,
+    };
+
+    let mut session = LeaderboardSession::new(
+        beatmap_info,
+        mode,
+        options.clone(),
+        scores,
+        calculator,
+        stats,
+        requester_osu_id,
+    );
+    session.fetched_at = fetched_at;
+
+    let page = match &options.player {
+        Some(player) => session
+            .find_player_page(player)
+            .ok_or_else(|| format!("{} has no score on this leaderboard", player))?,
+        None => options.page,
+    };
+
+    Ok((session, page))
+}
+
+// The beatmap and all scores of the board, before filtering by mods
+async fn fetch_leaderboard(
+    source: &dyn OsuDataSource,
+    beatmap_id: &str,
+    options: &LeaderboardOptions,
+) -> Result<(Beatmap, Vec<Score>), String> {
+    let beatmap_info = match source.fetch_beatmap_info(beatmap_id, options.mode).await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch beatmap info. Check if the beatmap ID is correct.",
+            ))
+        }
+    };
+
+    let mode = options.mode.unwrap_or(beatmap_info.mode);
+
+    match fetch_board_scores(source, beatmap_id, mode, options).await {
+        Ok(scores) => Ok((beatmap_info, scores)),
+        Err(e) => Err(api_error_message(
+            e,
+            "Failed to fetch scores. Check if the beatmap ID is correct.",
+        )),
+    }
+}
+
+// The images are still useful without pp, so calculation failures aren't fatal
+async fn get_calculator(
+    source: &dyn OsuDataSource,
+    beatmap: &Beatmap,
+    mode: GameMode,
+) -> Option<PerformanceCalculator> {
+    match source.fetch_osu_file(beatmap).await {
+        Ok(osu_file) => PerformanceCalculator::new(&osu_file, mode),
+        Err(e) => {
+            println!("Failed to get beatmap file {}: {}", beatmap.id, e);
+            None
+        }
+    }
+}
+
+pub async fn handle_leaderboard_page(
+    source: &dyn OsuDataSource,
+    session_id: u64,
+    page: usize,
+) -> Result<CommandReply, String> {
+    let session = leaderboard_pages::get_session(session_id)
+        .ok_or_else(|| LEADERBOARD_EXPIRED.to_string())?;
+
+    render_leaderboard_page(source, session_id, &session, page).await
+}
+
+pub async fn handle_leaderboard_player(
+    source: &dyn OsuDataSource,
+    session_id: u64,
+    player: &str,
+) -> Result<CommandReply, String> {
+    let session = leaderboard_pages::get_session(session_id)
+        .ok_or_else(|| LEADERBOARD_EXPIRED.to_string())?;
+
+    let page = session
+        .find_player_page(player)
+        .ok_or_else(|| format!("{} has no score on this leaderboard", player))?;
+
+    render_leaderboard_page(source, session_id, &session, page).await
+}
+
+const LEADERBOARD_EXPIRED: &str = "This leaderboard has expired. Please run the command again.";
+
+async fn render_leaderboard_page(
+    source: &dyn OsuDataSource,
+    session_id: u64,
+    session: &LeaderboardSession,
+    page: usize,
+) -> Result<CommandReply, String> {
+    let page_count = session.page_count();
+    let page = page.clamp(1, page_count);
+
+    let start = (page - 1) * PAGE_SIZE;
+    let end = (start + PAGE_SIZE).min(session.scores.len());
+
+    // The requester's score is pinned below the page when it isn't on it
+    let requester_index = session.requester_index();
+    let pinned_index = requester_index.filter(|i| !(start..end).contains(i));
+
+    let indices: Vec<usize> = (start..end).chain(pinned_index).collect();
+    let scores: Vec<&Score> = indices.iter().map(|i| &session.scores[*i]).collect();
+    let ranked = !matches!(session.options.board, LeaderboardType::Player(_));
+
+    let mut avatars = source.get_avatars_bytes_array(&scores).await.into_iter();
+
+    let mut rows: Vec<LeaderboardRow> = indices
+        .iter()
+        .zip(scores)
+        .map(|(i, score)| LeaderboardRow {
+            score,
+            avatar: avatars.next().unwrap_or_default(),
+            pp: session.calculator.as_ref().map(|c| c.score_pp(score)),
+            position: ranked.then_some(i + 1),
+            highlighted: Some(*i) == requester_index,
+        })
+        .collect();
+    let pinned = pinned_index.and_then(|_| rows.pop());
+
+    let beatmap = &session.beatmap;
+    let star_mods = session.options.mods.star_mods();
+    let title = session.options.board.title();
+    let stars = format_stars(beatmap, session.stats.as_ref(), &star_mods);
+    let table = generate_leaderboard(
+        &rows,
+        pinned.as_ref(),
+        beatmap,
+        &LeaderboardPage {
+            title: &title,
+            stars: &stars,
+            mode: session.mode,
+            scoring: session.options.scoring,
+        },
+    );
+
+    // Later pages are rendered from the same scores, so they are just as old
+    let age = session.fetched_at.elapsed();
+    let age_note = format!(
+        "-# Scores fetched {}, use --refresh for the latest\n",
+        match age.as_secs() {
+            0 => "just now".to_string(),
+            _ => format!("{} ago", format_age(age)),
+        }
+    );
+
+    Ok(CommandReply {
+        content: format!(
+            "[**{} - {} [{}]**](<https://osu.ppy.sh/beatmapsets/{}#{}/{}>)\n{}\n{}",
+            beatmap.beatmapset.artist,
+            beatmap.beatmapset.title,
+            beatmap.version,
+            beatmap.beatmapset_id,
+            session.mode.as_str(),
+            beatmap.id,
+            format_beatmap_stats(beatmap, session.stats.as_ref(), &star_mods),
+            age_note
+        ),
+        attachment: Some(CreateAttachment::bytes(table, "lb.png")),
+        components: page_buttons(session_id, page, page_count),
+    })
+}
+
+// e.g. "45s" or "3m"
+fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        secs if secs < 60 => format!("{}s", secs),
+        secs => format!("{}m", secs / 60),
+    }
+}
+
+pub const PAGE_BUTTON_PREFIX: &str = "lb-page";
+pub const FIND_PLAYER_PREFIX: &str = "lb-find";
+
+// ◀ 2/5 ▶ and a button opening the player search, custom IDs carry the session and target page
+fn page_buttons(session_id: u64, page: usize, page_count: usize) -> Vec<CreateActionRow> {
+    if page_count <= 1 {
+        return Vec::new();
+    }
+
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!(
+            "{}:{}:{}",
+            PAGE_BUTTON_PREFIX,
+            session_id,
+            page - 1
+        ))
+        .label("◀")
+        .style(ButtonStyle::Secondary)
+        .disabled(page <= 1),
+        CreateButton::new(format!("{}:{}:current", PAGE_BUTTON_PREFIX, session_id))
+            .label(format!("{}/{}", page, page_count))
+            .style(ButtonStyle::Secondary)
+            .disabled(true),
+        CreateButton::new(format!(
+            "{}:{}:{}",
+            PAGE_BUTTON_PREFIX,
+            session_id,
+            page + 1
+        ))
+        .label("▶")
+        .style(ButtonStyle::Secondary)
+        .disabled(page >= page_count),
+        CreateButton::new(format!("{}:{}", FIND_PLAYER_PREFIX, session_id))
+            .label("Find player")
+            .style(ButtonStyle::Primary),
+    ])]
+}
+
+// e.g. "★7.14 HDDT • 2:17 • 270 BPM • CS4 AR10.33 OD10.08 HP5 • Mapped by peppy • Ranked"
+// with `stats` calculated for `mods`, or osu!'s nomod values if there are no stats
+fn format_beatmap_stats(beatmap: &Beatmap, stats: Option<&AdjustedStats>, mods: &Mods) -> String {
+    let nomod = AdjustedStats::nomod(beatmap);
+    let adjusted = stats.unwrap_or(&nomod);
+    let length = (beatmap.total_length as f64 / adjusted.clock_rate).round() as u32;
+    let round = |value: f64| (value * 100.0).round() / 100.0;
+
+    format!(
+        "{} • {}:{:02} • {:.0} BPM • CS{} AR{} OD{} HP{} • Mapped by {} • {}",
+        format_stars(beatmap, stats, mods),
+        length / 60,
+        length % 60,
+        beatmap.bpm as f64 * adjusted.clock_rate,
+        round(adjusted.cs),
+        round(adjusted.ar),
+        round(adjusted.od),
+        round(adjusted.hp),
+        beatmap.mapper(),
+        beatmap.status.as_str()
+    )
+}
+
+// e.g. "★7.14 HDDT", labelled nomod when the mods couldn't be applied
+fn format_stars(beatmap: &Beatmap, stats: Option<&AdjustedStats>, mods: &Mods) -> String {
+    match stats {
+        Some(stats) if mods.is_empty() => format!("★{:.2}", stats.stars),
+        Some(stats) => format!("★{:.2} {}", stats.stars, mods),
+        None if mods.is_empty() => format!("★{:.2}", beatmap.difficulty_rating),
+        None => format!("★{:.2} nomod", beatmap.difficulty_rating),
+    }
+}
+
+pub async fn handle_beatmap_lb(
+    source: &dyn OsuDataSource,
+    beatmap: BeatmapRef,
+    options: &LeaderboardOptions,
+) -> Result<CommandReply, String> {
+    let (beatmap_id, mode) = resolve_beatmap_ref(source, beatmap, options.mode).await?;
+    let options = LeaderboardOptions {
+        mode,
+        ..options.clone()
+    };
+    handle_generate_lb(source, &beatmap_id.to_string(), &options).await
+}
+
+pub async fn handle_beatmap_server_lb(
+    source: &dyn OsuDataSource,
+    http: &Http,
+    guild_id: Option<GuildId>,
+    beatmap: BeatmapRef,
+    options: &LeaderboardOptions,
+) -> Result<CommandReply, String> {
+    let guild_id =
+        guild_id.ok_or_else(|| "Server leaderboards only work inside a server".to_string())?;
+
+    let osu_ids = get_linked_member_osu_ids(http, guild_id).await?;
+    if osu_ids.is_empty() {
+        return Err(
+            "Nobody in this server has connected their osu! account yet. Use `!connect {osu_id}`"
+                .to_string(),
+        );
+    }
+
+    let options = LeaderboardOptions {
+        board: LeaderboardType::Server(osu_ids),
+        ..options.clone()
+    };
+    handle_beatmap_lb(source, beatmap, &options).await
+}
+
+async fn get_linked_member_osu_ids(http: &Http, guild_id: GuildId) -> Result<Vec<i64>, String> {
+    let users = match database::get_all_users().await {
+        Ok(u) => u,
+        Err(_) => return Err("An unknown error occured. Please try again later.".to_string()),
+    };
+
+    let member_ids = match list_member_ids(http, guild_id).await {
+        Ok(ids) => ids,
+        Err(e) => {
+            // Listing members needs the privileged server members intent, without it the
+            // linked users are checked one by one
+            println!("Failed to list members of server {}: {:?}", guild_id, e);
+            let discord_ids: Vec<u64> = users.iter().map(|u| u.id as u64).collect();
+            check_member_ids(http, guild_id, &discord_ids).await?
+        }
+    };
+
+    Ok(users
+        .iter()
+        .filter(|u| member_ids.contains(&(u.id as u64)))
+        .map(|u| u.osu_id)
+        .collect())
+}
+
+// Discord returns at most 1000 members per request
+const MEMBERS_PAGE_SIZE: u64 = 1000;
+const MEMBER_CHECKS_IN_FLIGHT: usize = 5;
+// Leaves most of the osu! rate limit to other commands while a server board loads
+const MEMBER_SCORE_REQUESTS_IN_FLIGHT: usize = 5;
+
+async fn list_member_ids(http: &Http, guild_id: GuildId) -> serenity::Result<HashSet<u64>> {
+    let mut ids = HashSet::new();
+    let mut after = None;
+
+    loop {
+        let members = guild_id
+            .members(http, Some(MEMBERS_PAGE_SIZE), after)
+            .await?;
+        ids.extend(members.iter().map(|m| m.user.id.get()));
+
+        match members.last() {
+            Some(last) if members.len() as u64 == MEMBERS_PAGE_SIZE => after = Some(last.user.id),
+            _ => return Ok(ids),
+        }
+    }
+}
+
+// Only a 404 means someone isn't a member, any other error fails the check
+async fn check_member_ids(
+    http: &Http,
+    guild_id: GuildId,
+    discord_ids: &[u64],
+) -> Result<HashSet<u64>, String> {
+    // Collected into futures first, a closure in the stream makes the future not Send
+    let checks: Vec<_> = discord_ids
+        .iter()
+        .map(|id| async move { (*id, guild_id.member(http, UserId::new(*id)).await) })
+        .collect();
+    let results: Vec<_> = stream::iter(checks)
+        .buffer_unordered(MEMBER_CHECKS_IN_FLIGHT)
+        .collect()
+        .await;
+
+    let mut ids = HashSet::new();
+    for (id, result) in results {
+        match result {
+            Ok(_) => {
+                ids.insert(id);
+            }
+            Err(e) if is_not_found(&e) => {}
+            Err(e) => {
+                println!(
+                    "Failed to check member {} of server {}: {:?}",
+                    id, guild_id, e
+                );
+                return Err(
+                    "Failed to get the members of this server. Please try again later.".to_string(),
+                );
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+fn is_not_found(error: &serenity::Error) -> bool {
+    match error {
+        serenity::Error::Http(HttpError::UnsuccessfulRequest(response)) => {
+            response.status_code.as_u16() == 404
+        }
+        _ => false,
+    }
+}
+
+async fn fetch_board_scores(
+    source: &dyn OsuDataSource,
+    beatmap_id: &str,
+    mode: GameMode,
+    options: &LeaderboardOptions,
+) -> Result<Vec<Score>, OsuApiError> {
+    let scoring = options.scoring;
+
+    match &options.board {
+        LeaderboardType::Global => {
+            source
+                .fetch_scores(beatmap_id, mode, scoring, ScoreBoard::Global)
+                .await
+        }
+        LeaderboardType::Country => {
+            source
+                .fetch_scores(beatmap_id, mode, scoring, ScoreBoard::Country)
+                .await
+        }
+        LeaderboardType::Friends => {
+            source
+                .fetch_scores(beatmap_id, mode, scoring, ScoreBoard::Friends)
+                .await
+        }
+        LeaderboardType::CountryCode(code) => {
+            let scores: Vec<Score> = source
+                .fetch_scores(beatmap_id, mode, scoring, ScoreBoard::Global)
+                .await?
+                .into_iter()
+                .filter(|s| s.user.country_code == *code)
+                .collect();
+
+            if scores.is_empty() {
+                return Err(OsuApiError::NotFound(format!(
+                    "No players from {} in the global top scores of this beatmap",
+                    code
+                )));
+            }
+
+            Ok(scores)
+        }
+        LeaderboardType::Server(osu_ids) => {
+            // Filtering by mods needs every score, the best one may not match
+            let all_scores = !matches!(options.mods, ModsFilter::Any);
+            fetch_server_scores(source, beatmap_id, osu_ids, mode, scoring, all_scores).await
+        }
+        LeaderboardType::Player(user) => {
+            let mut scores = source
+                .fetch_user_beatmap_scores(beatmap_id, user, mode, scoring)
+                .await?;
+            scores.sort_by_key(|s| std::cmp::Reverse(s.total_score(scoring)));
+            Ok(scores)
+        }
+    }
+}
+
+// Best score, or with `all_scores` every score, of the given players on the beatmap, sorted
+// like a leaderboard. Players whose scores fail to load are left out.
+async fn fetch_server_scores(
+    source: &dyn OsuDataSource,
+    beatmap_id: &str,
+    osu_ids: &[i64],
+    mode: GameMode,
+    scoring: ScoringMode,
+    all_scores: bool,
+) -> Result<Vec<Score>, OsuApiError> {
+    let requests: Vec<_> = osu_ids
+        .iter()
+        .map(|osu_id| async move {
+            let result =
+                fetch_member_scores(source, beatmap_id, *osu_id, mode, scoring, all_scores).await;
+            (*osu_id, result)
+        })
+        .collect();
+    let results: Vec<_> = stream::iter(requests)
+        .buffer_unordered(MEMBER_SCORE_REQUESTS_IN_FLIGHT)
+        .collect()
+        .await;
+
+    let mut scores = Vec::new();
+    let mut last_error = None;
+    for (osu_id, result) in results {
+        match result {
+            Ok(member_scores) => scores.extend(member_scores),
+            Err(OsuApiError::NotFound(_)) => {}
+            Err(e) => {
+                println!("Failed to fetch scores of user {}: {}", osu_id, e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    if scores.is_empty() {
+        return Err(last_error.unwrap_or_else(|| {
+            OsuApiError::NotFound("Nobody in this server has a score on this beatmap".to_string())
+        }));
+    }
+
+    scores.sort_by_key(|s| std::cmp::Reverse(s.total_score(scoring)));
+    Ok(scores)
+}
+
+async fn fetch_member_scores(
+    source: &dyn OsuDataSource,
+    beatmap_id: &str,
+    osu_id: i64,
+    mode: GameMode,
+    scoring: ScoringMode,
+    all_scores: bool,
+) -> Result<Vec<Score>, OsuApiError> {
+    let best = source
+        .fetch_user_beatmap_score(beatmap_id, osu_id, mode, scoring)
+        .await?;
+
+    match all_scores {
+        true => {
+            source
+                .fetch_user_beatmap_scores(beatmap_id, &best.user, mode, scoring)
+                .await
+        }
+        false => Ok(vec![best]),
+    }
+}
+
+pub fn parse_beatmap_arg(beatmap: &str) -> Result<BeatmapRef, String> {
+    parse_beatmap_ref(beatmap)
+        .ok_or_else(|| "Invalid beatmap. Provide a beatmap ID or an osu! beatmap link.".to_string())
+}
+
+// Resolves a beatmap reference to a single difficulty. Beatmapset links without a
+// difficulty resolve to the hardest difficulty of the requested mode.
+pub async fn resolve_beatmap_ref(
+    source: &dyn OsuDataSource,
+    beatmap_ref: BeatmapRef,
+    mode: Option<GameMode>,
+) -> Result<(i64, Option<GameMode>), String> {
+    match beatmap_ref {
+        BeatmapRef::Beatmap {
+            id,
+            mode: link_mode,
+        } => Ok((id, mode.or(link_mode))),
+        BeatmapRef::Beatmapset {
+            id,
+            mode: link_mode,
+        } => {
+            let mode = mode.or(link_mode);
+            let difficulties = match source.fetch_beatmapset(id, mode).await {
+                Ok(d) => d,
+                Err(e) => {
+                    return Err(api_error_message(
+                        e,
+                        "Failed to fetch beatmapset info. Please try again later.",
+                    ))
+                }
+            };
+
+            let hardest = difficulties
+                .iter()
+                .max_by(|a, b| a.difficulty_rating.total_cmp(&b.difficulty_rating))
+                .map(|b| b.id);
+
+            match hardest {
+                Some(beatmap_id) => Ok((beatmap_id, mode)),
+                None => {
+                    Err("No difficulty found in this beatmapset for the given mode".to_string())
+                }
+            }
+        }
+    }
+}
+
+pub async fn handle_recent_country_lb(
+    source: &dyn OsuDataSource,
+    discord_id: u64,
+    user_arg: &str,
+    options: &LeaderboardOptions,
+) -> Result<CommandReply, String> {
+    let user = match user_arg.is_empty() {
+        true => get_linked_osu_id(discord_id).await?.to_string(),
+        false => user_arg.to_string(),
+    };
+
+    let recent_scores = get_recent_scores(source, &user, options.mode).await?;
+    let recent = &recent_scores[0];
+
+    let options = LeaderboardOptions {
+        mode: Some(recent.mode),
+        ..options.clone()
+    };
+    handle_generate_lb(source, &recent.beatmap.id.to_string(), &options).await
+}
+
+pub async fn handle_recent_score(
+    source: &dyn OsuDataSource,
+    discord_id: u64,
+    user_arg: &str,
+    options: &LeaderboardOptions,
+) -> Result<CommandReply, String> {
+    let user = match user_arg.is_empty() {
+        true => get_linked_osu_id(discord_id).await?.to_string(),
+        false => user_arg.to_string(),
+    };
+
+    let recent_scores = get_recent_scores(source, &user, options.mode).await?;
+    let recent = &recent_scores[0];
+    let score = &recent.score;
+
+    let beatmap = match source
+        .fetch_beatmap_info(&recent.beatmap.id.to_string(), Some(recent.mode))
+        .await
+    {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch beatmap info. Please try again later.",
+            ))
+        }
+    };
+
+    let avatar = source
+        .get_avatars_bytes_array(&[score])
+        .await
+        .pop()
+        .unwrap_or_default();
+
+    let calculator = get_calculator(source, &beatmap, recent.mode).await;
+    let mods = Mods::from_score(&score.mods);
+    let stats = calculator.as_ref().map(|c| c.adjusted_stats(&mods));
+    let stars = stats
+        .as_ref()
+        .map_or(beatmap.difficulty_rating as f64, |s| s.stars);
+
+    let pp = match (&calculator, recent.passed) {
+        (Some(c), true) => Some(c.score_pp(score)),
+        (Some(c), false) => Some(c.failed_score_pp(score)),
+        (None, _) => recent.pp.map(|pp| pp as f64),
+    };
+    let completion = match recent.passed {
+        true => None,
+        false => calculator.as_ref().map(|c| c.completion(score)),
+    };
+
+    let card = generate_recent_card(&RecentCard {
+        score,
+        beatmap: &beatmap,
+        mode: recent.mode,
+        scoring: options.scoring,
+        avatar,
+        stars,
+        pp,
+        fc_pp: calculator.as_ref().map(|c| c.fc_pp(score)),
+        completion,
+        try_count: count_tries(&recent_scores),
+    });
+
+    Ok(CommandReply {
+        content: format!(
+            "[**{} - {} [{}]**](<https://osu.ppy.sh/beatmapsets/{}#{}/{}>)\n{}\n",
+            beatmap.beatmapset.artist,
+            beatmap.beatmapset.title,
+            beatmap.version,
+            beatmap.beatmapset_id,
+            recent.mode.as_str(),
+            beatmap.id,
+            format_beatmap_stats(&beatmap, stats.as_ref(), &mods)
+        ),
+        attachment: Some(CreateAttachment::bytes(card, "recent.png")),
+        components: Vec::new(),
+    })
+}
+
+// Never empty, the data source reports users without recent scores as not found
+async fn get_recent_scores(
+    source: &dyn OsuDataSource,
+    user: &str,
+    mode: Option<GameMode>,
+) -> Result<Vec<RecentScore>, String> {
+    match source.get_user_recent(user, mode).await {
+        Ok(r) => Ok(r),
+        Err(e) => Err(api_error_message(
+            e,
+            "An unknown error occured. Please try again later.",
+        )),
+    }
+}
+
+// Consecutive plays of the same beatmap with the same mods and rate, counting the latest one
+fn count_tries(recent_scores: &[RecentScore]) -> usize {
+    let mods = |s: &RecentScore| Mods::from_score(&s.score.mods);
+
+    let Some(latest) = recent_scores.first() else {
+        return 0;
+    };
+    recent_scores
+        .iter()
+        .take_while(|s| s.beatmap.id == latest.beatmap.id && mods(s) == mods(latest))
+        .count()
+}
+
+pub async fn handle_compare(
+    source: &dyn OsuDataSource,
+    discord_id: u64,
+    user_arg: &str,
+    beatmap: BeatmapRef,
+    options: &LeaderboardOptions,
+) -> Result<CommandReply, String> {
+    let user = match user_arg.is_empty() {
+        true => get_linked_osu_id(discord_id).await?.to_string(),
+        false => user_arg.to_string(),
+    };
+
+    let user = match source.fetch_user(&user).await {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch user. Check if the username is correct.",
+            ))
+        }
+    };
+
+    let options = LeaderboardOptions {
+        board: LeaderboardType::Player(user),
+        ..options.clone()
+    };
+    handle_beatmap_lb(source, beatmap, &options).await
+}
+
+pub async fn handle_top_plays(
+    source: &dyn OsuDataSource,
+    discord_id: u64,
+    user_arg: &str,
+    options: &TopPlaysOptions,
+) -> Result<CommandReply, String> {
+    let user = match user_arg.is_empty() {
+        true => get_linked_osu_id(discord_id).await?.to_string(),
+        false => user_arg.to_string(),
+    };
+
+    let user = match source.fetch_user(&user).await {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch user. Check if the username is correct.",
+            ))
+        }
+    };
+
+    let best = match source.fetch_user_best(user.id, options.mode).await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch top plays. Please try again later.",
+            ))
+        }
+    };
+    let total = best.len();
+    let mode = options.mode.unwrap_or(best[0].mode);
+
+    // Positions stay those of the whole top 100
+    let mut plays: Vec<TopPlayRow> = best
+        .iter()
+        .enumerate()
+        .map(|(i, play)| TopPlayRow {
+            play,
+            position: i + 1,
+        })
+        .filter(|row| {
+            let score = &row.play.score;
+            let date = score.ended_at.split('T').next().unwrap_or("");
+
+            options.mods.matches(&Mods::from_score(&score.mods))
+                && options
+                    .rank
+                    .as_ref()
+                    .is_none_or(|rank| rank_matches(&score.rank, rank))
+                && options
+                    .from
+                    .as_ref()
+                    .is_none_or(|from| date >= from.as_str())
+                && options.to.as_ref().is_none_or(|to| date <= to.as_str())
+        })
+        .collect();
+
+    if plays.is_empty() {
+        return Err(format!(
+            "None of {}'s top plays match these filters",
+            user.username
+        ));
+    }
+
+    match options.sort {
+        TopPlaysSort::Pp => {}
+        TopPlaysSort::Accuracy => {
+            plays.sort_by(|a, b| b.play.score.accuracy.total_cmp(&a.play.score.accuracy))
+        }
+        TopPlaysSort::Combo => plays.sort_by_key(|r| std::cmp::Reverse(r.play.score.max_combo)),
+        TopPlaysSort::Date => {
+            plays.sort_by(|a, b| b.play.score.ended_at.cmp(&a.play.score.ended_at))
+        }
+    }
+
+    let page_count = plays.len().div_ceil(PAGE_SIZE);
+    let page = options.page.clamp(1, page_count);
+    let start = (page - 1) * PAGE_SIZE;
+    let rows = &plays[start..(start + PAGE_SIZE).min(plays.len())];
+
+    let image = generate_top_plays(
+        rows,
+        &format!("Top plays of {}", user.username),
+        &format!(
+            "{} • By {} • Page {}/{}",
+            mode.name(),
+            options.sort.name(),
+            page,
+            page_count
+        ),
+    );
+
+    Ok(CommandReply {
+        content: format!(
+            "[**{}**](<https://osu.ppy.sh/users/{}/{}>) • {} of {} top plays\n",
+            user.username,
+            user.id,
+            mode.as_str(),
+            plays.len(),
+            total
+        ),
+        attachment: Some(CreateAttachment::bytes(image, "top.png")),
+        components: Vec::new(),
+    })
+}
+
+// osu! ranks are XH/X for SS and SH/S for S, with H for the silver versions
+fn rank_matches(score_rank: &str, rank: &str) -> bool {
+    match rank {
+        "SS" => matches!(score_rank, "X" | "XH"),
+        "S" => matches!(score_rank, "S" | "SH"),
+        rank => score_rank == rank,
+    }
+}
+
+pub async fn handle_profile(
+    source: &dyn OsuDataSource,
+    discord_id: u64,
+    user_arg: &str,
+    mode: Option<GameMode>,
+) -> Result<CommandReply, String> {
+    let user = match user_arg.is_empty() {
+        true => get_linked_osu_id(discord_id).await?.to_string(),
+        false => user_arg.to_string(),
+    };
+
+    let profile = match source.fetch_user_profile(&user, mode).await {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch user. Check if the username is correct.",
+            ))
+        }
+    };
+
+    let mode = mode.unwrap_or(profile.playmode);
+    let card = generate_profile_card(&profile, mode);
+
+    Ok(CommandReply {
+        content: format!(
+            "[**{}**](<https://osu.ppy.sh/users/{}/{}>)\n",
+            profile.user.username,
+            profile.user.id,
+            mode.as_str()
+        ),
+        attachment: Some(CreateAttachment::bytes(card, "profile.png")),
+        components: Vec::new(),
+    })
+}
+
+pub async fn handle_beatmap_info(
+    source: &dyn OsuDataSource,
+    beatmap: BeatmapRef,
+    mods: &str,
+    mode: Option<GameMode>,
+) -> Result<CommandReply, String> {
+    let mods = Mods::parse(mods)?;
+
+    let (beatmap_id, mode) = resolve_beatmap_ref(source, beatmap, mode).await?;
+    let beatmap = match source
+        .fetch_beatmap_info(&beatmap_id.to_string(), mode)
+        .await
+    {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(api_error_message(
+                e,
+                "Failed to fetch beatmap info. Check if the beatmap ID is correct.",
+            ))
+        }
+    };
+
+    let mode = mode.unwrap_or(beatmap.mode);
+    let calculator = get_calculator(source, &beatmap, mode).await;
+
+    // Without the beatmap file only the nomod values from osu! are known
+    let (stats, mods_name, note) = match calculator.map(|c| c.adjusted_stats(&mods)) {
+        Some(stats) => (stats, mods.to_string(), String::new()),
+        None if mods.is_empty() => (AdjustedStats::nomod(&beatmap), String::new(), String::new()),
+        None => (
+            AdjustedStats::nomod(&beatmap),
+            String::new(),
+            format!(
+                "-# Couldn't load the beatmap file to apply {}, showing nomod stats\n",
+                mods
+            ),
+        ),
+    };
+
+    let card = BeatmapCard {
+        beatmap: &beatmap,
+        mods: &mods_name,
+        stars: stats.stars,
+        cs: stats.cs,
+        ar: stats.ar,
+        od: stats.od,
+        hp: stats.hp,
+        bpm: beatmap.bpm as f64 * stats.clock_rate,
+        length: (beatmap.total_length as f64 / stats.clock_rate).round() as u32,
+    };
+    let image = generate_beatmap_card(&card);
+
+    Ok(CommandReply {
+        content: format!(
+            "[**{} - {} [{}]**](<https://osu.ppy.sh/beatmapsets/{}#{}/{}>)\n{}",
+            beatmap.beatmapset.artist,
+            beatmap.beatmapset.title,
+            beatmap.version,
+            beatmap.beatmapset_id,
+            mode.as_str(),
+            beatmap.id,
+            note
+        ),
+        attachment: Some(CreateAttachment::bytes(image, "map.png")),
+        components: Vec::new(),
+    })
+}
+
+pub async fn handle_connect(
+    discord_id: u64,
+    name: &str,
+    osu_id: i64,
+) -> Result<CommandReply, String> {
+    match database::insert_user(discord_id as i64, name, osu_id).await {
+        Ok(_) => Ok(CommandReply::text(
+            "Successfully connected your osu! account",
+        )),
+        Err(e) => {
+            let error_msg = match e {
+                sqlx::Error::Database(db_error) if db_error.code() == Some("1555".into()) => {
+                    "This Discord account is already connected to an osu! account"
+                }
+                _ => "An unknown error occured. Please try again later.",
+            };
+            Err(error_msg.to_string())
+        }
+    }
+}
+
+pub async fn get_linked_osu_id(discord_id: u64) -> Result<i64, String> {
+    match database::get_user_by_id(discord_id as i64).await {
+        Ok(u) => Ok(u.osu_id),
+        Err(e) => {
+            let error_msg = match e {
+                database::UserError::UserNotFound => "Failed to get user. Did you connect your osu! account with `!connect {osu_id}`?".to_string(),
+                database::UserError::DatabaseError(e) => e,
+            };
+            Err(error_msg)
+        }
+    }
+}
+
+fn api_error_message(error: OsuApiError, fallback: &str) -> String {
+    match error {
+        OsuApiError::NotFound(e) => e,
+        OsuApiError::RateLimited(_) => {
+            "osu! is rate limiting the bot right now. Please try again in a minute.".to_string()
+        }
+        OsuApiError::Server(_) => {
+            "osu! is having issues right now. Please try again later.".to_string()
+        }
+        OsuApiError::Unauthorized(e) => {
+            println!("osu! API rejected the bot's credentials: {}", e);
+            "The bot's osu! credentials were rejected. Please contact the bot owner.".to_string()
+        }
+        e => {
+            println!("osu! API error: {}", e);
+            fallback.to_string()
+        }
+    }
+}
+
+// Completions of the mods typed so far for options taking plain mods, like /map's
+pub fn suggest_mods(input: &str) -> Vec<String> {
+    let input = input.trim().to_uppercase();
+    complete_mods("", input.strip_prefix('+').unwrap_or(&input))
+}
+
+// Completions for mods filters, keeping a leading + or - filter operator
+pub fn suggest_mods_filter(input: &str) -> Vec<String> {
+    let input = input.trim().to_uppercase();
+    match input.chars().next() {
+        Some('+' | '-') => complete_mods(&input[..1], &input[1..]),
+        _ => complete_mods("", &input),
+    }
+}
+
+fn complete_mods(operator: &str, typed: &str) -> Vec<String> {
+    // Mods are ASCII, anything else can't be split into acronyms
+    if !typed.is_ascii() || typed.starts_with(['+', '-']) {
+        return Vec::new();
+    }
+
+    // The last character may be the start of another acronym
+    let (complete, partial) = match Mods::parse(typed) {
+        Ok(_) => (typed, ""),
+        Err(_) => typed.split_at(typed.len().saturating_sub(1)),
+    };
+    let Ok(mods) = Mods::parse(complete) else {
+        return Vec::new();
+    };
+
+    let mut suggestions: Vec<String> = SUGGESTED_MODS
+        .iter()
+        .filter(|m| m.starts_with(partial) && !mods.contains(m))
+        .map(|m| format!("{}{}{}", operator, complete, m))
+        .collect();
+
+    if partial.is_empty() && !complete.is_empty() {
+        suggestions.insert(0, format!("{}{}", operator, complete));
+    }
+
+    suggestions.truncate(25);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::data_source::FixtureDataSource;
+    use serenity::all::json;
+
+    fn fixtures() -> FixtureDataSource {
+        FixtureDataSource::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures"))
+    }
+
+    fn lb_options(mods: &str, board: LeaderboardType) -> LeaderboardOptions {
+        let mut options = LeaderboardOptions::new(mods, None, true, None).unwrap();
+        options.board = board;
+        options.refresh = true;
+        options
+    }
+
+    async fn board_players(mods: &str, board: LeaderboardType) -> Result<Vec<String>, String> {
+        let (session, _) = load_leaderboard(&fixtures(), "75", &lb_options(mods, board)).await?;
+        Ok(session
+            .scores
+            .iter()
+            .map(|s| s.user.username.clone())
+            .collect())
+    }
+
+    fn top_options(mods: &str, rank: Option<&str>, from: Option<&str>) -> TopPlaysOptions {
+        TopPlaysOptions::new(mods, None, rank, from, None, None).unwrap()
+    }
+
+    #[test]
+    fn top_plays_dates_must_exist_and_be_in_order() {
+        let options = TopPlaysOptions::new("", None, None, Some("2024-2-29"), None, None);
+        assert_eq!(options.unwrap().from.as_deref(), Some("2024-02-29"));
+
+        for date in [
+            "2023-02-29",
+            "2024-02-31",
+            "2023-04-31",
+            "2024-13-01",
+            "0-01-01",
+        ] {
+            let options = TopPlaysOptions::new("", None, None, Some(date), None, None);
+            assert!(options.is_err(), "{}", date);
+        }
+
+        let inverted =
+            TopPlaysOptions::new("", None, None, Some("2024-05-02"), Some("2024-05-01"), None);
+        assert!(inverted.is_err());
+        let same_day =
+            TopPlaysOptions::new("", None, None, Some("2024-05-01"), Some("2024-5-1"), None);
+        assert!(same_day.is_ok());
+    }
+
+    #[tokio::test]
+    async fn country_board() {
+        let players = board_players("", LeaderboardType::Country).await.unwrap();
+        assert_eq!(players, vec!["peppy", "BanchoBot", "Example"]);
+
+        let options = lb_options("", LeaderboardType::Country);
+        let reply = handle_generate_lb(&fixtures(), "75", &options)
+            .await
+            .unwrap();
+        assert!(reply.attachment.is_some());
+        assert!(reply.components.is_empty());
+        assert!(reply.content.contains("beatmapsets/1#osu/75"));
+    }
+
+    #[tokio::test]
+    async fn global_board() {
+        let players = board_players("", LeaderboardType::Global).await.unwrap();
+        assert_eq!(
+            players,
+            vec!["Cookiezi", "WhiteCat", "peppy", "BanchoBot", "Example"]
+        );
+    }
+
+    #[tokio::test]
+    async fn country_code_board_filters_global_top() {
+        let players = board_players("", LeaderboardType::CountryCode("DE".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(players, vec!["WhiteCat"]);
+
+        let error = board_players("", LeaderboardType::CountryCode("JP".to_string()))
+            .await
+            .unwrap_err();
+        assert!(error.contains("No players from JP"));
+    }
+
+    #[tokio::test]
+    async fn server_board_skips_members_without_scores() {
+        let players = board_players("", LeaderboardType::Server(vec![3, 2, 999]))
+            .await
+            .unwrap();
+        assert_eq!(players, vec!["peppy", "BanchoBot"]);
+
+        let error = board_players("", LeaderboardType::Server(vec![999]))
+            .await
+            .unwrap_err();
+        assert_eq!(error, "Nobody in this server has a score on this beatmap");
+    }
+
+    #[tokio::test]
+    async fn server_board_skips_members_that_fail_to_load() {
+        // The fixture of user 5 can't be parsed
+        let players = board_players("", LeaderboardType::Server(vec![5, 2]))
+            .await
+            .unwrap();
+        assert_eq!(players, vec!["peppy"]);
+
+        let error = board_players("", LeaderboardType::Server(vec![5]))
+            .await
+            .unwrap_err();
+        assert!(error.starts_with("Failed to fetch scores"));
+    }
+
+    #[tokio::test]
+    async fn server_board_filters_every_score_of_the_members() {
+        // The best scores are HD and nomod, HDDT and HR are only found in all scores
+        let players = board_players("HDDT", LeaderboardType::Server(vec![2, 3]))
+            .await
+            .unwrap();
+        assert_eq!(players, vec!["peppy"]);
+
+        let players = board_players("HR", LeaderboardType::Server(vec![2, 3]))
+            .await
+            .unwrap();
+        assert_eq!(players, vec!["BanchoBot"]);
+
+        // One score per member
+        let players = board_players("-EZ", LeaderboardType::Server(vec![2, 3]))
+            .await
+            .unwrap();
+        assert_eq!(players, vec!["peppy", "BanchoBot"]);
+    }
+
+    #[tokio::test]
+    async fn player_board_lists_every_score() {
+        let user = fixtures().fetch_user("2").await.unwrap();
+        let (session, _) = load_leaderboard(
+            &fixtures(),
+            "75",
+            &lb_options("", LeaderboardType::Player(user)),
+        )
+        .await
+        .unwrap();
+
+        let mods: Vec<String> = session
+            .scores
+            .iter()
+            .map(|s| Mods::from_score(&s.mods).to_string())
+            .collect();
+        assert_eq!(mods, vec!["HD", "HDDT1.25x", ""]);
+    }
+
+    #[tokio::test]
+    async fn mods_filters() {
+        let friends = || LeaderboardType::Friends;
+
+        let exact = board_players("HDDT", friends()).await.unwrap();
+        assert_eq!(exact, vec!["Mrekk", "Rafis", "Freddie Benson"]);
+
+        let nc_as_dt = board_players("DT", friends()).await.unwrap();
+        assert_eq!(nc_as_dt, vec!["Accolibed"]);
+
+        let nomod = board_players("NM", friends()).await.unwrap();
+        assert_eq!(nomod, vec!["aetrna", "Cookiezi"]);
+
+        let include = board_players("+HD", friends()).await.unwrap();
+        assert_eq!(
+            include,
+            vec!["Mrekk", "lifeline", "Vaxei", "Rafis", "Freddie Benson"]
+        );
+
+        let exclude = board_players("-HDEZ", friends()).await.unwrap();
+        assert_eq!(exclude, vec!["Accolibed", "aetrna", "WhiteCat", "Cookiezi"]);
+
+        let error = board_players("FL", friends()).await.unwrap_err();
+        assert_eq!(error, "No scores found with the specified mods");
+    }
+
+    #[tokio::test]
+    async fn paging() {
+        let source = fixtures();
+        let mut options = lb_options("", LeaderboardType::Friends);
+
+        let (session, page) = load_leaderboard(&source, "75", &options).await.unwrap();
+        assert_eq!(session.page_count(), 2);
+        assert_eq!(page, 1);
+
+        options.player = Some("freddie_benson".to_string());
+        let (_, page) = load_leaderboard(&source, "75", &options).await.unwrap();
+        assert_eq!(page, 2);
+
+        options.player = Some("nobody".to_string());
+        let error = load_leaderboard(&source, "75", &options).await.err();
+        assert_eq!(
+            error.as_deref(),
+            Some("nobody has no score on this leaderboard")
+        );
+
+        options.player = None;
+        options.page = 5;
+        let reply = handle_generate_lb(&source, "75", &options).await.unwrap();
+        let buttons = json::to_string(&reply.components).unwrap();
+        assert!(buttons.contains("\"label\":\"2/2\""));
+
+        // The session ID is carried by the page buttons
+        let session_id: u64 = buttons
+            .split(&format!("{}:", PAGE_BUTTON_PREFIX))
+            .nth(1)
+            .and_then(|rest| rest.split(':').next())
+            .and_then(|id| id.parse().ok())
+            .unwrap();
+        let reply = handle_leaderboard_page(&source, session_id, 1)
+            .await
+            .unwrap();
+        let buttons = json::to_string(&reply.components).unwrap();
+        assert!(buttons.contains("\"label\":\"1/2\""));
+        assert!(reply.content.contains("-# Scores fetched"));
+
+        let error = handle_leaderboard_page(&source, 0, 1).await.err();
+        assert_eq!(error.as_deref(), Some(LEADERBOARD_EXPIRED));
+    }
+
+    #[tokio::test]
+    async fn beatmap_stats_follow_the_mods() {
+        let beatmap = fixtures().fetch_beatmap_info("75", None).await.unwrap();
+        let hddt = Mods::parse("HDDT").unwrap();
+
+        let stats = AdjustedStats {
+            stars: 3.456,
+            cs: 4.0,
+            ar: 8.0,
+            od: 8.333333,
+            hp: 6.0,
+            clock_rate: 1.5,
+        };
+        let adjusted = format_beatmap_stats(&beatmap, Some(&stats), &hddt);
+        assert!(adjusted.starts_with("★3.46 HDDT • 1:35 • 180 BPM • CS4 AR8 OD8.33 HP6"));
+
+        // Without a calculator the nomod values are labelled as such
+        let nomod = format_beatmap_stats(&beatmap, None, &hddt);
+        assert!(nomod.starts_with("★2.55 nomod • 2:22 • 120 BPM • CS4 AR6 OD6 HP6"));
+    }
+
+    #[tokio::test]
+    async fn beatmap_info_notes_mods_it_couldnt_apply() {
+        // There's no beatmap file for 76 to calculate the mods with
+        let beatmap = BeatmapRef::Beatmap { id: 76, mode: None };
+
+        let reply = handle_beatmap_info(&fixtures(), beatmap, "HDDT", None)
+            .await
+            .unwrap();
+        assert!(reply
+            .content
+            .contains("Couldn't load the beatmap file to apply HDDT"));
+
+        let reply = handle_beatmap_info(&fixtures(), beatmap, "", None)
+            .await
+            .unwrap();
+        assert!(!reply.content.contains("Couldn't load"));
+    }
+
+    #[tokio::test]
+    async fn count_tries_of_recent_plays() {
+        let recent = get_recent_scores(&fixtures(), "2", None).await.unwrap();
+
+        // Two failed HDDT tries, then an HD pass of the same beatmap
+        assert_eq!(count_tries(&recent), 2);
+        assert_eq!(count_tries(&recent[1..]), 1);
+        assert_eq!(count_tries(&[]), 0);
+
+        let options = lb_options("", LeaderboardType::Country);
+        let reply = handle_recent_score(&fixtures(), 0, "peppy", &options)
+            .await
+            .unwrap();
+        assert!(reply.content.contains("beatmapsets/1#osu/75"));
+    }
+
+    #[tokio::test]
+    async fn top_plays_filters() {
+        let top = |options: TopPlaysOptions| async move {
+            handle_top_plays(&fixtures(), 0, "2", &options)
+                .await
+                .map(|reply| reply.content)
+        };
+
+        let all = top(top_options("", None, None)).await.unwrap();
+        assert!(all.contains("• 5 of 5 top plays"));
+
+        let include = top(top_options("+HD", None, None)).await.unwrap();
+        assert!(include.contains("• 3 of 5 top plays"));
+
+        let nomod = top(top_options("NM", None, None)).await.unwrap();
+        assert!(nomod.contains("• 1 of 5 top plays"));
+
+        // S also matches silver S
+        let rank = top(top_options("", Some("s"), None)).await.unwrap();
+        assert!(rank.contains("• 2 of 5 top plays"));
+
+        let from = top(top_options("", None, Some("2024-1-1"))).await.unwrap();
+        assert!(from.contains("• 2 of 5 top plays"));
+
+        let error = top(top_options("EZ", None, None)).await.unwrap_err();
+        assert_eq!(error, "None of peppy's top plays match these filters");
+    }
+
+    #[test]
+    fn country_code_boards_are_titled_as_filtered_global_top() {
+        let board = LeaderboardType::from_arg("de").unwrap();
+        assert_eq!(board.title(), "DE players in global top");
+    }
+
+    #[test]
+    fn suggests_completions_of_typed_mods() {
+        let suggestions = suggest_mods_filter("+hdd");
+        assert_eq!(suggestions, vec!["+HDDT".to_string()]);
+
+        let suggestions = suggest_mods_filter("HD");
+        assert_eq!(suggestions[0], "HD");
+        assert!(suggestions.contains(&"HDDT".to_string()));
+        assert!(!suggestions.contains(&"HDHD".to_string()));
+
+        let suggestions = suggest_mods("DT1.3h");
+        assert_eq!(suggestions[0], "DT1.3HD");
+    }
+
+    // /map only takes plain mods, so it's never offered filter operators
+    #[test]
+    fn suggests_only_plain_mods_for_the_beatmap_card() {
+        for input in ["", "h", "+h", "HDD", "DT1.3"] {
+            for suggestion in suggest_mods(input) {
+                assert!(!suggestion.starts_with(['+', '-']), "{}", suggestion);
+                assert!(Mods::parse(&suggestion).is_ok(), "{}", suggestion);
+            }
+        }
+        assert!(suggest_mods("-HD").is_empty());
+        assert!(suggest_mods_filter("++HD").is_empty());
+    }
+
+    #[test]
+    fn suggests_nothing_for_non_ascii_input() {
+        assert!(suggest_mods_filter("hé").is_empty());
+        assert!(suggest_mods_filter("-é").is_empty());
+        assert!(suggest_mods("HDé").is_empty());
+    }
+}
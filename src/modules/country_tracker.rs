@@ -0,0 +1,250 @@
+use super::data_source::data_source;
+use super::database::{self, CountryFirst};
+use super::generate_lb::format_number;
+use super::osu_api::{GameMode, OsuApiError, Score, ScoreBoard, ScoringMode};
+
+use serenity::all::{ChannelId, Http};
+use std::collections::HashSet;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+const DEFAULT_INTERVAL_MINUTES: u64 = 10;
+const TOP_PLAYS_PER_USER: usize = 10;
+// With REQUEST_SPACING, checking this many beatmaps of linked users takes about 8 minutes
+const MAX_LINKED_BEATMAPS: usize = 100;
+
+// The tracker shares the osu! rate limit with commands, so it sends at most 12 requests a
+// minute and leaves the rest to them
+const REQUEST_SPACING: Duration = Duration::from_secs(5);
+
+// Discord sends ready again after reconnecting, but only one tracker should run
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+// Read from the environment, tracking is off without TRACK_CHANNEL_ID
+struct TrackerConfig {
+    channel_id: ChannelId,
+    beatmaps: Vec<(i64, GameMode)>,
+    linked_users: bool,
+    interval: Duration,
+}
+
+impl TrackerConfig {
+    fn from_env() -> Option<Self> {
+        let channel_id = env::var("TRACK_CHANNEL_ID").ok()?;
+        let channel_id = match channel_id.parse::<u64>() {
+            Ok(id) if id > 0 => ChannelId::new(id),
+            _ => {
+                println!("Invalid TRACK_CHANNEL_ID {}", channel_id);
+                return None;
+            }
+        };
+
+        // Beatmap IDs, optionally with a mode like 75:taiko
+        let beatmaps = env::var("TRACK_BEATMAPS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let (id, mode) = entry.split_once(':').unwrap_or((entry, "osu"));
+                let beatmap = id.parse::<i64>().ok().zip(GameMode::from_arg(mode));
+                if beatmap.is_none() {
+                    println!("Ignoring invalid tracked beatmap {}", entry);
+                }
+                beatmap
+            })
+            .collect();
+
+        let linked_users = env::var("TRACK_LINKED_USERS").is_ok_and(|v| v == "true" || v == "1");
+
+        let minutes = env::var("TRACK_INTERVAL_MINUTES")
+            .ok()
+            .and_then(|m| m.parse::<u64>().ok())
+            .filter(|m| *m > 0)
+            .unwrap_or(DEFAULT_INTERVAL_MINUTES);
+
+        Some(Self {
+            channel_id,
+            beatmaps,
+            linked_users,
+            interval: Duration::from_secs(minutes * 60),
+        })
+    }
+}
+
+pub fn start_country_tracker(http: Arc<Http>) {
+    let Some(config) = TrackerConfig::from_env() else {
+        return;
+    };
+
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    println!(
+        "Tracking country #1s of {} beatmaps{} every {} minutes",
+        config.beatmaps.len(),
+        if config.linked_users {
+            " and top plays of linked users"
+        } else {
+            ""
+        },
+        config.interval.as_secs() / 60
+    );
+
+    tokio::spawn(async move {
+        // A check can take longer than the interval, the next one waits for it to finish
+        let mut interval = tokio::time::interval(config.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+
+            for (beatmap_id, mode) in tracked_beatmaps(&config).await {
+                tokio::time::sleep(REQUEST_SPACING).await;
+                if let Err(e) = check_beatmap(&http, config.channel_id, beatmap_id, mode).await {
+                    println!(
+                        "Failed to check country #1 of beatmap {}: {}",
+                        beatmap_id, e
+                    );
+                }
+            }
+        }
+    });
+}
+
+// Configured beatmaps and the beatmaps of linked users' best top plays. Only a few top plays
+// are taken, so one check of every beatmap stays within the interval.
+async fn tracked_beatmaps(config: &TrackerConfig) -> Vec<(i64, GameMode)> {
+    let mut seen = HashSet::new();
+    let mut beatmaps = config.beatmaps.clone();
+    beatmaps.retain(|beatmap| seen.insert(*beatmap));
+
+    if !config.linked_users {
+        return beatmaps;
+    }
+
+    let users = match database::get_all_users().await {
+        Ok(u) => u,
+        Err(e) => {
+            println!("Failed to get linked users: {:?}", e);
+            Vec::new()
+        }
+    };
+
+    let mut added = 0;
+    for user in users {
+        if added >= MAX_LINKED_BEATMAPS {
+            break;
+        }
+
+        tokio::time::sleep(REQUEST_SPACING).await;
+        match data_source().fetch_user_best(user.osu_id, None).await {
+            Ok(scores) => {
+                for score in scores.iter().take(TOP_PLAYS_PER_USER) {
+                    let beatmap = (score.beatmap.id, score.mode);
+                    if added < MAX_LINKED_BEATMAPS && seen.insert(beatmap) {
+                        beatmaps.push(beatmap);
+                        added += 1;
+                    }
+                }
+            }
+            Err(OsuApiError::NotFound(_)) => {}
+            Err(e) => println!("Failed to get top plays of {}: {}", user.osu_id, e),
+        }
+    }
+
+    beatmaps
+}
+
+async fn check_beatmap(
+    http: &Http,
+    channel_id: ChannelId,
+    beatmap_id: i64,
+    mode: GameMode,
+) -> Result<(), String> {
+    // Same scores as the default !cs leaderboard
+    let scoring = ScoringMode::Legacy;
+    let scores = match data_source()
+        .fetch_scores(&beatmap_id.to_string(), mode, scoring, ScoreBoard::Country)
+        .await
+    {
+        Ok(s) => s,
+        Err(OsuApiError::NotFound(_)) => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let Some(top) = scores.first() else {
+        return Ok(());
+    };
+
+    let current = CountryFirst {
+        beatmap_id,
+        mode: mode.as_str().to_string(),
+        user_id: top.user.id,
+        username: top.user.username.clone(),
+        score: top.total_score(scoring),
+    };
+
+    let previous = database::get_country_first(beatmap_id, mode.as_str())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if previous
+        .as_ref()
+        .is_some_and(|p| p.user_id == current.user_id && p.score == current.score)
+    {
+        return Ok(());
+    }
+
+    // Nobody was sniped the first time a beatmap is seen or when the #1 improved their own
+    // score. The new #1 is only saved once announced, so a failed announcement is retried.
+    if let Some(previous) = previous.filter(|p| p.user_id != current.user_id) {
+        announce_snipe(http, channel_id, top, &previous, mode).await?;
+    }
+
+    database::set_country_first(&current)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn announce_snipe(
+    http: &Http,
+    channel_id: ChannelId,
+    score: &Score,
+    previous: &CountryFirst,
+    mode: GameMode,
+) -> Result<(), String> {
+    let beatmap = data_source()
+        .fetch_beatmap_info(&previous.beatmap_id.to_string(), Some(mode))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mods = match score.mods.is_empty() {
+        true => "NM".to_string(),
+        false => score.mods.iter().map(|m| m.acronym.as_str()).collect(),
+    };
+
+    let content = format!(
+        "**{}** sniped **{}** on [**{} - {} [{}]**](<https://osu.ppy.sh/beatmapsets/{}#{}/{}>) with {} ({:.2}%, {})",
+        score.user.username,
+        previous.username,
+        beatmap.beatmapset.artist,
+        beatmap.beatmapset.title,
+        beatmap.version,
+        beatmap.beatmapset_id,
+        mode.as_str(),
+        beatmap.id,
+        format_number(score.total_score(ScoringMode::Legacy)),
+        score.accuracy * 100.0,
+        mods
+    );
+
+    channel_id
+        .say(http, content)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
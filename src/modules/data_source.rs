@@ -0,0 +1,408 @@
+use super::osu_api::{
+    Beatmap, BeatmapUserScore, BeatmapsetResponse, BestScore, GameMode, OsuApiError, OsuClient,
+    RecentScore, Score, ScoreBoard, ScoreResponse, ScoringMode, User, UserProfile,
+};
+
+use once_cell::sync::OnceCell;
+use serde::de::DeserializeOwned;
+use serenity::all::json;
+use serenity::async_trait;
+use std::env;
+use std::path::{Path, PathBuf};
+
+// Everything the commands need from osu!, so they can run against recorded data
+#[async_trait]
+pub trait OsuDataSource: Send + Sync {
+    async fn fetch_scores(
+        &self,
+        beatmap_id: &str,
+        mode: GameMode,
+        scoring: ScoringMode,
+        board: ScoreBoard,
+    ) -> Result<Vec<Score>, OsuApiError>;
+
+    async fn fetch_user_beatmap_score(
+        &self,
+        beatmap_id: &str,
+        user_id: i64,
+        mode: GameMode,
+        scoring: ScoringMode,
+    ) -> Result<Score, OsuApiError>;
+
+    // Every score of the user on the beatmap, e.g. one per mod combination
+    async fn fetch_user_beatmap_scores(
+        &self,
+        beatmap_id: &str,
+        user: &User,
+        mode: GameMode,
+        scoring: ScoringMode,
+    ) -> Result<Vec<Score>, OsuApiError>;
+
+    async fn fetch_beatmap_info(
+        &self,
+        beatmap_id: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Beatmap, OsuApiError>;
+
+    async fn fetch_beatmapset(
+        &self,
+        beatmapset_id: i64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<Beatmap>, OsuApiError>;
+
+    async fn fetch_osu_file(&self, beatmap: &Beatmap) -> Result<Vec<u8>, OsuApiError>;
+
+    // In the order of `scores`, empty for avatars that couldn't be loaded
+    async fn get_avatars_bytes_array(&self, scores: &[&Score]) -> Vec<Vec<u8>>;
+
+    async fn fetch_user(&self, user: &str) -> Result<User, OsuApiError>;
+
+    async fn get_user_id(&self, user: &str) -> Result<String, OsuApiError>;
+
+    // With the avatar and cover images, in the user's default mode if `mode` is None
+    async fn fetch_user_profile(
+        &self,
+        user: &str,
+        mode: Option<GameMode>,
+    ) -> Result<UserProfile, OsuApiError>;
+
+    // Sorted by pp like on the user's profile
+    async fn fetch_user_best(
+        &self,
+        user_id: i64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<BestScore>, OsuApiError>;
+
+    // Most recent first, including failed plays
+    async fn get_user_recent(
+        &self,
+        user: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<RecentScore>, OsuApiError>;
+}
+
+static DATA_SOURCE: OnceCell<Box<dyn OsuDataSource>> = OnceCell::new();
+
+// Uses recorded responses from OSU_FIXTURES_DIR when set, the live osu! API otherwise
+pub fn initialize_data_source() {
+    let source: Box<dyn OsuDataSource> = match env::var("OSU_FIXTURES_DIR") {
+        Ok(dir) => {
+            println!("Serving osu! data from fixtures in {}", dir);
+            Box::new(FixtureDataSource::new(dir))
+        }
+        Err(_) => Box::new(OsuClient::new()),
+    };
+
+    if DATA_SOURCE.set(source).is_err() {
+        println!("osu! data source was already initialized");
+    }
+}
+
+pub fn data_source() -> &'static dyn OsuDataSource {
+    DATA_SOURCE
+        .get_or_init(|| Box::new(OsuClient::new()))
+        .as_ref()
+}
+
+// Serves recorded API responses from disk:
+//   scores/{beatmap_id}-{mode}-{type}.json
+//                                       beatmap scores response, type as in ScoreBoard
+//   user_scores/{beatmap_id}-{mode}-{user_id}.json
+//                                       API v2 beatmap user score
+//   user_scores/{beatmap_id}-{mode}-{user_id}-all.json
+//                                       API v2 beatmap user scores
+//   beatmaps/{beatmap_id}.json          API v2 beatmap
+//   beatmapsets/{beatmapset_id}.json    API v2 beatmapset
+//   covers/{beatmapset_id}.png          beatmap cover (optional)
+//   osu/{beatmap_id}.osu                beatmap file
+//   avatars/{user_id}.png               user avatar (optional)
+//   users/{user}.json                   API v2 user, by lowercase name or ID
+//   recent/{user_id}.json               API v2 recent scores
+//   best/{user_id}.json                 API v2 best scores
+//   profiles/{user}-{mode}.json         API v2 user with statistics, by lowercase name or ID,
+//                                       mode defaults to osu
+//   profile_covers/{user_id}.png        profile cover (optional)
+pub struct FixtureDataSource {
+    dir: PathBuf,
+}
+
+impl FixtureDataSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn read_bytes(&self, path: &Path) -> Result<Vec<u8>, OsuApiError> {
+        std::fs::read(self.dir.join(path))
+            .map_err(|_| OsuApiError::NotFound(format!("Fixture {} not found", path.display())))
+    }
+
+    // Keeps names typed into commands from reaching files outside of the fixtures
+    fn checked_name(name: &str) -> Result<&str, OsuApiError> {
+        match name.contains(['/', '\\']) || name.contains("..") {
+            true => Err(OsuApiError::NotFound(format!(
+                "Invalid fixture name {}",
+                name
+            ))),
+            false => Ok(name),
+        }
+    }
+
+    fn read_json<T: DeserializeOwned>(&self, path: &Path) -> Result<T, OsuApiError> {
+        let bytes = self.read_bytes(path)?;
+        json::from_slice(&bytes).map_err(|e| OsuApiError::ParseError(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl OsuDataSource for FixtureDataSource {
+    async fn fetch_scores(
+        &self,
+        beatmap_id: &str,
+        mode: GameMode,
+        _scoring: ScoringMode,
+        board: ScoreBoard,
+    ) -> Result<Vec<Score>, OsuApiError> {
+        let path = PathBuf::from(format!(
+            "scores/{}-{}-{}.json",
+            Self::checked_name(beatmap_id)?,
+            mode.as_str(),
+            board.as_param()
+        ));
+        let scores = self.read_json::<ScoreResponse>(&path)?.scores;
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(
+                "No scores found on this leaderboard".to_string(),
+            ));
+        }
+
+        Ok(scores)
+    }
+
+    async fn fetch_user_beatmap_score(
+        &self,
+        beatmap_id: &str,
+        user_id: i64,
+        mode: GameMode,
+        _scoring: ScoringMode,
+    ) -> Result<Score, OsuApiError> {
+        let path = PathBuf::from(format!(
+            "user_scores/{}-{}-{}.json",
+            Self::checked_name(beatmap_id)?,
+            mode.as_str(),
+            user_id
+        ));
+
+        Ok(self.read_json::<BeatmapUserScore>(&path)?.score)
+    }
+
+    async fn fetch_user_beatmap_scores(
+        &self,
+        beatmap_id: &str,
+        user: &User,
+        mode: GameMode,
+        _scoring: ScoringMode,
+    ) -> Result<Vec<Score>, OsuApiError> {
+        let path = PathBuf::from(format!(
+            "user_scores/{}-{}-{}-all.json",
+            Self::checked_name(beatmap_id)?,
+            mode.as_str(),
+            user.id
+        ));
+        let mut scores = self.read_json::<ScoreResponse>(&path)?.scores;
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "{} has no scores on this beatmap",
+                user.username
+            )));
+        }
+
+        for score in scores.iter_mut() {
+            score.user = user.clone();
+        }
+
+        Ok(scores)
+    }
+
+    async fn fetch_beatmap_info(
+        &self,
+        beatmap_id: &str,
+        _mode: Option<GameMode>,
+    ) -> Result<Beatmap, OsuApiError> {
+        let path = PathBuf::from(format!("beatmaps/{}.json", Self::checked_name(beatmap_id)?));
+        let mut beatmap = self.read_json::<Beatmap>(&path)?;
+
+        let cover_path = PathBuf::from(format!("covers/{}.png", beatmap.beatmapset_id));
+        beatmap.cover = self.read_bytes(&cover_path).unwrap_or_default();
+
+        Ok(beatmap)
+    }
+
+    async fn fetch_beatmapset(
+        &self,
+        beatmapset_id: i64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<Beatmap>, OsuApiError> {
+        let path = PathBuf::from(format!("beatmapsets/{}.json", beatmapset_id));
+        let beatmaps = self
+            .read_json::<BeatmapsetResponse>(&path)?
+            .into_difficulties(mode);
+
+        if beatmaps.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "Beatmapset {} not found",
+                beatmapset_id
+            )));
+        }
+
+        Ok(beatmaps)
+    }
+
+    async fn fetch_osu_file(&self, beatmap: &Beatmap) -> Result<Vec<u8>, OsuApiError> {
+        self.read_bytes(&PathBuf::from(format!("osu/{}.osu", beatmap.id)))
+    }
+
+    async fn get_avatars_bytes_array(&self, scores: &[&Score]) -> Vec<Vec<u8>> {
+        scores
+            .iter()
+            .map(|s| {
+                let path = PathBuf::from(format!("avatars/{}.png", s.user.id));
+                self.read_bytes(&path).unwrap_or_default()
+            })
+            .collect()
+    }
+
+    async fn fetch_user(&self, user: &str) -> Result<User, OsuApiError> {
+        let path = PathBuf::from(format!(
+            "users/{}.json",
+            Self::checked_name(&user.to_lowercase())?
+        ));
+        self.read_json::<User>(&path)
+    }
+
+    async fn get_user_id(&self, user: &str) -> Result<String, OsuApiError> {
+        if let Ok(id) = user.parse::<i64>() {
+            return Ok(id.to_string());
+        }
+
+        Ok(self.fetch_user(user).await?.id.to_string())
+    }
+
+    async fn fetch_user_profile(
+        &self,
+        user: &str,
+        mode: Option<GameMode>,
+    ) -> Result<UserProfile, OsuApiError> {
+        let mode = mode.unwrap_or(GameMode::Osu);
+        let path = PathBuf::from(format!(
+            "profiles/{}-{}.json",
+            Self::checked_name(&user.to_lowercase())?,
+            mode.as_str()
+        ));
+        let mut profile = self.read_json::<UserProfile>(&path)?;
+
+        let user_id = profile.user.id;
+        profile.avatar_image = self
+            .read_bytes(&PathBuf::from(format!("avatars/{}.png", user_id)))
+            .unwrap_or_default();
+        profile.cover_image = self
+            .read_bytes(&PathBuf::from(format!("profile_covers/{}.png", user_id)))
+            .unwrap_or_default();
+
+        Ok(profile)
+    }
+
+    async fn fetch_user_best(
+        &self,
+        user_id: i64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<BestScore>, OsuApiError> {
+        let path = PathBuf::from(format!("best/{}.json", user_id));
+        let scores: Vec<BestScore> = self
+            .read_json::<Vec<BestScore>>(&path)?
+            .into_iter()
+            .filter(|s| mode.is_none_or(|m| s.mode == m))
+            .collect();
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "No top plays for user {}",
+                user_id
+            )));
+        }
+
+        Ok(scores)
+    }
+
+    async fn get_user_recent(
+        &self,
+        user: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<RecentScore>, OsuApiError> {
+        let user_id = self.get_user_id(user).await?;
+
+        let path = PathBuf::from(format!("recent/{}.json", user_id));
+        let scores: Vec<RecentScore> = self
+            .read_json::<Vec<RecentScore>>(&path)?
+            .into_iter()
+            .filter(|s| mode.is_none_or(|m| s.mode == m))
+            .collect();
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "No recent scores for user {}",
+                user
+            )));
+        }
+
+        Ok(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> FixtureDataSource {
+        FixtureDataSource::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures"))
+    }
+
+    #[tokio::test]
+    async fn recent_scores() {
+        let recent = fixtures().get_user_recent("peppy", None).await.unwrap();
+        assert_eq!(recent[0].beatmap.id, 75);
+        assert!(fixtures()
+            .get_user_recent("peppy", Some(GameMode::Taiko))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn names_cannot_leave_the_fixtures() {
+        let source = fixtures();
+        assert!(source.get_user_id("peppy").await.is_ok());
+
+        for name in ["../users/peppy", "..\\users\\peppy", "a/../peppy", ".."] {
+            assert!(source.get_user_id(name).await.is_err(), "{}", name);
+            assert!(
+                source.fetch_user_profile(name, None).await.is_err(),
+                "{}",
+                name
+            );
+            assert!(
+                source.fetch_beatmap_info(name, None).await.is_err(),
+                "{}",
+                name
+            );
+            assert!(
+                source
+                    .fetch_scores(name, GameMode::Osu, ScoringMode::Lazer, ScoreBoard::Country)
+                    .await
+                    .is_err(),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -4,7 +4,7 @@ use sqlx::{migrate::MigrateDatabase, Pool, Sqlite, SqlitePool};
 #[derive(Debug, sqlx::FromRow)]
 #[allow(dead_code)]
 pub struct User {
-    id: i64,
+    pub id: i64,
     name: String,
     pub osu_id: i64,
 }
@@ -61,3 +61,60 @@ pub async fn get_user_by_id(id: i64) -> Result<User, UserError> {
         })?
         .ok_or(UserError::UserNotFound)
 }
+
+pub async fn get_all_users() -> Result<Vec<User>, UserError> {
+    let pool = DB_POOL
+        .get()
+        .ok_or_else(|| UserError::DatabaseError("Database pool not initialized".to_string()))?;
+
+    sqlx::query_as!(User, "SELECT * FROM users")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            println!("Database error: {:?}", e);
+            UserError::DatabaseError(e.to_string())
+        })
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct CountryFirst {
+    pub beatmap_id: i64,
+    pub mode: String,
+    pub user_id: i64,
+    pub username: String,
+    pub score: i64,
+}
+
+pub async fn get_country_first(
+    beatmap_id: i64,
+    mode: &str,
+) -> Result<Option<CountryFirst>, sqlx::Error> {
+    let pool = DB_POOL.get().ok_or_else(|| sqlx::Error::PoolClosed)?;
+
+    sqlx::query_as!(
+        CountryFirst,
+        "SELECT * FROM country_firsts WHERE beatmap_id = ? AND mode = ?",
+        beatmap_id,
+        mode
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn set_country_first(first: &CountryFirst) -> Result<(), sqlx::Error> {
+    let pool = DB_POOL.get().ok_or_else(|| sqlx::Error::PoolClosed)?;
+
+    sqlx::query!(
+        "INSERT INTO country_firsts (beatmap_id, mode, user_id, username, score) VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (beatmap_id, mode) DO UPDATE SET user_id = excluded.user_id, username = excluded.username, score = excluded.score",
+        first.beatmap_id,
+        first.mode,
+        first.user_id,
+        first.username,
+        first.score
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,680 @@
+use super::generate_lb::{
+    calc_text_width, draw_background, draw_header, draw_mod_badges, draw_position,
+    draw_profile_image, draw_rank, draw_statistics, format_date, format_number, get_judgements,
+    load_fonts, mod_badges_width, Fonts, CANVAS_WIDTH, CELL_HEIGHT, HEADER_HEIGHT, PADDING,
+};
+use super::mods::Mods;
+use super::osu_api::{
+    Beatmap, BeatmapStatus, BestScore, GameMode, Score, ScoringMode, UserProfile,
+};
+use skia_safe::{surfaces, Canvas, Color, EncodedImageFormat, Font, Paint, Surface};
+
+const RECENT_CARD_HEIGHT: f32 = 240.0;
+const PROFILE_CARD_HEIGHT: f32 = 330.0;
+const BEATMAP_CARD_HEIGHT: f32 = 250.0;
+
+fn draw_text(canvas: &Canvas, font: &Font, text: &str, color: Color, x: f32, y: f32) {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.set_anti_alias(true);
+
+    canvas.draw_str(text, (x, y), font, &paint);
+}
+
+fn draw_text_right(canvas: &Canvas, font: &Font, text: &str, color: Color, x: f32, y: f32) {
+    let text_width = calc_text_width(font, text);
+    draw_text(canvas, font, text, color, x - text_width, y);
+}
+
+// Cuts the text short with an ellipsis until it fits into `max_width`
+fn fit_text(font: &Font, text: &str, max_width: f32) -> String {
+    if calc_text_width(font, text) <= max_width {
+        return text.to_string();
+    }
+
+    let mut chars: Vec<char> = text.chars().collect();
+    while chars.pop().is_some() {
+        let shortened = format!("{}…", chars.iter().collect::<String>().trim_end());
+        if calc_text_width(font, &shortened) <= max_width {
+            return shortened;
+        }
+    }
+
+    String::new()
+}
+
+// Small grey label with the value below it
+fn draw_stat(
+    canvas: &Canvas,
+    fonts: &Fonts,
+    label: &str,
+    value: &str,
+    color: Color,
+    x: f32,
+    y: f32,
+) {
+    draw_text(
+        canvas,
+        &fonts.smaller,
+        label,
+        Color::from_rgb(170, 170, 170), // #aaaaaa
+        x,
+        y,
+    );
+    draw_text(canvas, &fonts.bold, value, color, x, y + 24.0);
+}
+
+fn encode_png(surface: &mut Surface) -> Vec<u8> {
+    let image = surface.image_snapshot();
+    let data = image
+        .encode_to_data(EncodedImageFormat::PNG)
+        .expect("Failed to encode image");
+
+    data.as_bytes().to_vec()
+}
+
+pub struct RecentCard<'a> {
+    pub score: &'a Score,
+    pub beatmap: &'a Beatmap,
+    pub mode: GameMode,
+    pub scoring: ScoringMode,
+    pub avatar: Vec<u8>,
+    pub stars: f64,
+    pub pp: Option<f64>,
+    pub fc_pp: Option<f64>,
+    // How far a failed play got, None for passes
+    pub completion: Option<f64>,
+    pub try_count: usize,
+}
+
+pub fn generate_recent_card(card: &RecentCard) -> Vec<u8> {
+    let (score, beatmap) = (card.score, card.beatmap);
+    let fonts = load_fonts();
+
+    let mut surface = surfaces::raster_n32_premul((CANVAS_WIDTH as i32, RECENT_CARD_HEIGHT as i32))
+        .expect("Failed to create surface");
+
+    let canvas = surface.canvas();
+    canvas.clear(Color::BLACK);
+
+    draw_background(canvas, &beatmap.cover, CANVAS_WIDTH, RECENT_CARD_HEIGHT);
+
+    let grey = Color::from_rgb(170, 170, 170); // #aaaaaa
+    let pink = Color::from_rgb(255, 102, 171); // #ff66ab
+
+    // Beatmap and player next to the avatar, with room for the rank on the right
+    draw_profile_image(canvas, card.avatar.clone(), PADDING, PADDING, 80.0, 10.0);
+
+    let text_x = PADDING + 95.0;
+    let text_width = CANVAS_WIDTH - text_x - PADDING - 80.0;
+
+    let title = format!(
+        "{} - {}",
+        beatmap.beatmapset.artist, beatmap.beatmapset.title
+    );
+    draw_text(
+        canvas,
+        &fonts.bold,
+        &fit_text(&fonts.bold, &title, text_width),
+        Color::WHITE,
+        text_x,
+        PADDING + 20.0,
+    );
+
+    let version = format!("[{}] ★{:.2}", beatmap.version, card.stars);
+    draw_text(
+        canvas,
+        &fonts.default,
+        &fit_text(&fonts.default, &version, text_width),
+        Color::WHITE,
+        text_x,
+        PADDING + 46.0,
+    );
+
+    let played_by = format!(
+        "Played by {} • {} • {}",
+        score.user.username,
+        format_date(&score.ended_at),
+        card.mode.name()
+    );
+    draw_text(
+        canvas,
+        &fonts.smaller,
+        &fit_text(&fonts.smaller, &played_by, text_width),
+        grey,
+        text_x,
+        PADDING + 72.0,
+    );
+
+    draw_rank(
+        canvas,
+        &fonts.large,
+        &score.rank,
+        CANVAS_WIDTH - PADDING - 70.0,
+        PADDING + 60.0,
+    );
+
+    let format_pp = |pp: Option<f64>| pp.map_or("-".to_string(), |pp| format!("{:.0}pp", pp));
+    let stats = [
+        (
+            "Score",
+            format_number(score.total_score(card.scoring)),
+            Color::WHITE,
+        ),
+        (
+            "Accuracy",
+            format!("{:.2}%", score.accuracy * 100.0),
+            Color::WHITE,
+        ),
+        (
+            "Combo",
+            format!("{}x / {}x", score.max_combo, beatmap.max_combo),
+            Color::WHITE,
+        ),
+        ("PP", format_pp(card.pp), pink),
+        ("If FC", format_pp(card.fc_pp), pink),
+    ];
+
+    let column_width = (CANVAS_WIDTH - 2.0 * PADDING) / stats.len() as f32;
+    for (i, (label, value, color)) in stats.iter().enumerate() {
+        draw_stat(
+            canvas,
+            &fonts,
+            label,
+            value,
+            *color,
+            PADDING + i as f32 * column_width,
+            PADDING + 115.0,
+        );
+    }
+
+    let mods = Mods::from_score(&score.mods);
+    // Mod badges take the place of the value below the label
+    let mods_text = match mods.is_empty() {
+        true => "NM",
+        false => "",
+    };
+    draw_stat(
+        canvas,
+        &fonts,
+        "Mods",
+        mods_text,
+        Color::WHITE,
+        PADDING,
+        PADDING + 180.0,
+    );
+    draw_mod_badges(canvas, &fonts.badge, &mods, PADDING, PADDING + 204.0);
+
+    draw_statistics(
+        canvas,
+        &fonts.smaller,
+        &get_judgements(&score.statistics, card.mode),
+        CANVAS_WIDTH / 2.0,
+        PADDING + 204.0,
+    );
+
+    let tries = match card.completion {
+        Some(completion) => format!(
+            "Try #{} • Failed at {:.0}%",
+            card.try_count,
+            completion * 100.0
+        ),
+        None => format!("Try #{}", card.try_count),
+    };
+    draw_text_right(
+        canvas,
+        &fonts.default,
+        &tries,
+        Color::WHITE,
+        CANVAS_WIDTH - PADDING,
+        PADDING + 204.0,
+    );
+
+    encode_png(&mut surface)
+}
+
+pub struct TopPlayRow<'a> {
+    pub play: &'a BestScore,
+    // Position in the user's top plays by pp
+    pub position: usize,
+}
+
+fn draw_top_play(canvas: &Canvas, fonts: &Fonts, row: &TopPlayRow, row_y: f32) {
+    let (score, play) = (&row.play.score, row.play);
+    let pink = Color::from_rgb(255, 102, 171); // #ff66ab
+
+    let position_width = draw_position(
+        canvas,
+        &fonts.bold,
+        row.position,
+        PADDING,
+        row_y + CELL_HEIGHT * 0.2,
+    );
+
+    let mods = Mods::from_score(&score.mods);
+    let mods_width = mod_badges_width(&fonts.badge, &mods);
+    draw_mod_badges(
+        canvas,
+        &fonts.badge,
+        &mods,
+        CANVAS_WIDTH - PADDING - mods_width,
+        row_y + CELL_HEIGHT * 0.2,
+    );
+
+    let name_x = PADDING + position_width + 6.0;
+    let name = format!(
+        "{} - {} [{}]",
+        play.beatmapset.artist, play.beatmapset.title, play.beatmap.version
+    );
+    let name_width = CANVAS_WIDTH - PADDING - name_x - mods_width - 12.0;
+    draw_text(
+        canvas,
+        &fonts.bold,
+        &fit_text(&fonts.bold, &name, name_width),
+        Color::WHITE,
+        name_x,
+        row_y + CELL_HEIGHT * 0.2,
+    );
+
+    let pp = play.pp.map_or("-".to_string(), |pp| format!("{:.0}pp", pp));
+    draw_text(
+        canvas,
+        &fonts.bold,
+        &pp,
+        pink,
+        PADDING,
+        row_y + CELL_HEIGHT / 2.0,
+    );
+
+    draw_text(
+        canvas,
+        &fonts.default,
+        &format!(
+            "{}x • ★{:.2}",
+            score.max_combo, play.beatmap.difficulty_rating
+        ),
+        Color::WHITE,
+        PADDING + 90.0,
+        row_y + CELL_HEIGHT / 2.0,
+    );
+
+    draw_text_right(
+        canvas,
+        &fonts.default,
+        &format!("{:.2}%", score.accuracy * 100.0),
+        Color::WHITE,
+        CANVAS_WIDTH - PADDING,
+        row_y + CELL_HEIGHT / 2.0,
+    );
+
+    draw_rank(
+        canvas,
+        &fonts.bold,
+        &score.rank,
+        PADDING,
+        row_y + CELL_HEIGHT * 0.75,
+    );
+
+    draw_statistics(
+        canvas,
+        &fonts.smaller,
+        &get_judgements(&score.statistics, play.mode),
+        CANVAS_WIDTH / 2.0,
+        row_y + CELL_HEIGHT * 0.75,
+    );
+
+    draw_text_right(
+        canvas,
+        &fonts.default,
+        &format_date(&score.ended_at),
+        Color::WHITE,
+        CANVAS_WIDTH - PADDING,
+        row_y + CELL_HEIGHT * 0.75,
+    );
+}
+
+// Rows like the leaderboard's, with the beatmap in place of the player
+pub fn generate_top_plays(rows: &[TopPlayRow], title: &str, subtitle: &str) -> Vec<u8> {
+    let fonts = load_fonts();
+    let canvas_height = PADDING + HEADER_HEIGHT + CELL_HEIGHT * rows.len() as f32;
+
+    let mut surface = surfaces::raster_n32_premul((CANVAS_WIDTH as i32, canvas_height as i32))
+        .expect("Failed to create surface");
+
+    let canvas = surface.canvas();
+    canvas.clear(Color::BLACK);
+
+    draw_header(canvas, &fonts.bold, title, subtitle);
+
+    rows.iter().enumerate().for_each(|(i, row)| {
+        let row_y = PADDING + HEADER_HEIGHT + i as f32 * CELL_HEIGHT;
+        draw_top_play(canvas, &fonts, row, row_y);
+    });
+
+    encode_png(&mut surface)
+}
+
+// 5123400 -> "59d 7h", shorter play times in hours and minutes
+fn format_play_time(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match days {
+        0 => format!("{}h {}m", hours, minutes),
+        days => format!("{}d {}h", days, hours),
+    }
+}
+
+fn draw_rank_history(canvas: &Canvas, ranks: &[u32], x: f32, y: f32, width: f32, height: f32) {
+    let points = rank_history_points(ranks, x, y, width, height);
+    if points.len() < 2 {
+        return;
+    }
+
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgb(255, 204, 34)); // #ffcc22
+    paint.set_anti_alias(true);
+    paint.set_stroke_width(2.0);
+
+    for line in points.windows(2) {
+        canvas.draw_line(line[0], line[1], &paint);
+    }
+}
+
+// Better ranks are drawn higher, like the graph on osu! profiles. Days without a rank are 0
+// and left out, the line connects the days around them.
+fn rank_history_points(ranks: &[u32], x: f32, y: f32, width: f32, height: f32) -> Vec<(f32, f32)> {
+    let ranked: Vec<(usize, u32)> = ranks
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, rank)| *rank > 0)
+        .collect();
+
+    let best = ranked.iter().map(|(_, rank)| *rank).min().unwrap_or(0) as f32;
+    let worst = ranked.iter().map(|(_, rank)| *rank).max().unwrap_or(0) as f32;
+    let range = (worst - best).max(1.0);
+    let day_width = width / ranks.len().saturating_sub(1).max(1) as f32;
+
+    ranked
+        .iter()
+        .map(|(day, rank)| {
+            (
+                x + *day as f32 * day_width,
+                y + (*rank as f32 - best) / range * height,
+            )
+        })
+        .collect()
+}
+
+pub fn generate_profile_card(profile: &UserProfile, mode: GameMode) -> Vec<u8> {
+    let (user, stats) = (&profile.user, &profile.statistics);
+    let fonts = load_fonts();
+    let grey = Color::from_rgb(170, 170, 170); // #aaaaaa
+
+    let mut surface =
+        surfaces::raster_n32_premul((CANVAS_WIDTH as i32, PROFILE_CARD_HEIGHT as i32))
+            .expect("Failed to create surface");
+
+    let canvas = surface.canvas();
+    canvas.clear(Color::BLACK);
+
+    draw_background(
+        canvas,
+        &profile.cover_image,
+        CANVAS_WIDTH,
+        PROFILE_CARD_HEIGHT,
+    );
+
+    draw_profile_image(
+        canvas,
+        profile.avatar_image.clone(),
+        PADDING,
+        PADDING,
+        100.0,
+        14.0,
+    );
+
+    let text_x = PADDING + 115.0;
+    let text_width = CANVAS_WIDTH - text_x - PADDING;
+
+    draw_text(
+        canvas,
+        &fonts.large,
+        &fit_text(&fonts.large, &user.username, text_width),
+        Color::WHITE,
+        text_x,
+        PADDING + 42.0,
+    );
+
+    let format_rank = |rank: Option<u32>| rank.map_or("-".to_string(), |r| format_number(r as i64));
+    draw_text(
+        canvas,
+        &fonts.bold,
+        &format!(
+            "#{} global • #{} {}",
+            format_rank(stats.global_rank),
+            format_rank(stats.country_rank),
+            user.country_code
+        ),
+        Color::WHITE,
+        text_x,
+        PADDING + 72.0,
+    );
+
+    draw_text(
+        canvas,
+        &fonts.smaller,
+        &format!(
+            "{} • Level {} ({}%)",
+            mode.name(),
+            stats.level.current,
+            stats.level.progress
+        ),
+        grey,
+        text_x,
+        PADDING + 96.0,
+    );
+
+    let profile_stats = [
+        ("PP", format_number(stats.pp.round() as i64)),
+        ("Accuracy", format!("{:.2}%", stats.hit_accuracy)),
+        ("Play count", format_number(stats.play_count as i64)),
+        ("Play time", format_play_time(stats.play_time)),
+    ];
+
+    let column_width = (CANVAS_WIDTH - 2.0 * PADDING) / profile_stats.len() as f32;
+    for (i, (label, value)) in profile_stats.iter().enumerate() {
+        draw_stat(
+            canvas,
+            &fonts,
+            label,
+            value,
+            Color::WHITE,
+            PADDING + i as f32 * column_width,
+            PADDING + 130.0,
+        );
+    }
+
+    // Grade letters in their colours with the counts next to them
+    let grades = [
+        ("XH", stats.grade_counts.ssh),
+        ("X", stats.grade_counts.ss),
+        ("SH", stats.grade_counts.sh),
+        ("S", stats.grade_counts.s),
+        ("A", stats.grade_counts.a),
+    ];
+
+    let grade_width = (CANVAS_WIDTH - 2.0 * PADDING) / grades.len() as f32;
+    for (i, (rank, count)) in grades.iter().enumerate() {
+        let x = PADDING + i as f32 * grade_width;
+        draw_rank(canvas, &fonts.bold, rank, x, PADDING + 190.0);
+        draw_text(
+            canvas,
+            &fonts.default,
+            &format_number(*count as i64),
+            Color::WHITE,
+            x + 32.0,
+            PADDING + 190.0,
+        );
+    }
+
+    draw_text(
+        canvas,
+        &fonts.smaller,
+        "Rank history",
+        grey,
+        PADDING,
+        PADDING + 225.0,
+    );
+
+    if let Some(history) = &profile.rank_history {
+        draw_rank_history(
+            canvas,
+            &history.data,
+            PADDING,
+            PADDING + 240.0,
+            CANVAS_WIDTH - 2.0 * PADDING,
+            PROFILE_CARD_HEIGHT - 2.0 * PADDING - 240.0,
+        );
+    }
+
+    encode_png(&mut surface)
+}
+
+// Values with mods applied, see performance::AdjustedStats
+pub struct BeatmapCard<'a> {
+    pub beatmap: &'a Beatmap,
+    pub mods: &'a str,
+    pub stars: f64,
+    pub cs: f64,
+    pub ar: f64,
+    pub od: f64,
+    pub hp: f64,
+    pub bpm: f64,
+    // In seconds
+    pub length: u32,
+}
+
+fn status_color(status: BeatmapStatus) -> Color {
+    match status {
+        BeatmapStatus::Ranked | BeatmapStatus::Approved => Color::from_rgb(179, 255, 102), // #b3ff66
+        BeatmapStatus::Qualified => Color::from_rgb(102, 204, 255), // #66ccff
+        BeatmapStatus::Loved => Color::from_rgb(255, 102, 171),     // #ff66ab
+        _ => Color::from_rgb(170, 170, 170),                        // #aaaaaa
+    }
+}
+
+pub fn generate_beatmap_card(card: &BeatmapCard) -> Vec<u8> {
+    let beatmap = card.beatmap;
+    let fonts = load_fonts();
+    let grey = Color::from_rgb(170, 170, 170); // #aaaaaa
+
+    let mut surface =
+        surfaces::raster_n32_premul((CANVAS_WIDTH as i32, BEATMAP_CARD_HEIGHT as i32))
+            .expect("Failed to create surface");
+
+    let canvas = surface.canvas();
+    canvas.clear(Color::BLACK);
+
+    draw_background(canvas, &beatmap.cover, CANVAS_WIDTH, BEATMAP_CARD_HEIGHT);
+
+    let status = beatmap.status.as_str();
+    let status_width = calc_text_width(&fonts.bold, status);
+    draw_text_right(
+        canvas,
+        &fonts.bold,
+        status,
+        status_color(beatmap.status),
+        CANVAS_WIDTH - PADDING,
+        PADDING + 20.0,
+    );
+
+    let text_width = CANVAS_WIDTH - 2.0 * PADDING - status_width - 12.0;
+    draw_text(
+        canvas,
+        &fonts.large,
+        &fit_text(&fonts.large, &beatmap.beatmapset.title, text_width),
+        Color::WHITE,
+        PADDING,
+        PADDING + 40.0,
+    );
+
+    draw_text(
+        canvas,
+        &fonts.default,
+        &fit_text(&fonts.default, &beatmap.beatmapset.artist, text_width),
+        Color::WHITE,
+        PADDING,
+        PADDING + 68.0,
+    );
+
+    let version = format!(
+        "[{}] mapped by {} • {}",
+        beatmap.version,
+        beatmap.mapper(),
+        beatmap.mode.name()
+    );
+    draw_text(
+        canvas,
+        &fonts.smaller,
+        &fit_text(&fonts.smaller, &version, CANVAS_WIDTH - 2.0 * PADDING),
+        grey,
+        PADDING,
+        PADDING + 92.0,
+    );
+
+    let stars = match card.mods {
+        "" => format!("★{:.2}", card.stars),
+        mods => format!("★{:.2} {}", card.stars, mods),
+    };
+    let stat_rows = [
+        [
+            ("Star rating", stars),
+            (
+                "Length",
+                format!("{}:{:02}", card.length / 60, card.length % 60),
+            ),
+            ("BPM", format!("{:.0}", card.bpm)),
+            ("Max combo", format!("{}x", beatmap.max_combo)),
+        ],
+        [
+            ("CS", format!("{:.1}", card.cs)),
+            ("AR", format!("{:.1}", card.ar)),
+            ("OD", format!("{:.1}", card.od)),
+            ("HP", format!("{:.1}", card.hp)),
+        ],
+    ];
+
+    let column_width = (CANVAS_WIDTH - 2.0 * PADDING) / 4.0;
+    for (row, stats) in stat_rows.iter().enumerate() {
+        for (i, (label, value)) in stats.iter().enumerate() {
+            draw_stat(
+                canvas,
+                &fonts,
+                label,
+                value,
+                Color::WHITE,
+                PADDING + i as f32 * column_width,
+                PADDING + 130.0 + row as f32 * 55.0,
+            );
+        }
+    }
+
+    encode_png(&mut surface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_history_leaves_out_days_without_a_rank() {
+        let points = rank_history_points(&[0, 100, 0, 200, 150], 0.0, 0.0, 40.0, 10.0);
+        assert_eq!(points, vec![(10.0, 0.0), (30.0, 10.0), (40.0, 5.0)]);
+
+        assert_eq!(
+            rank_history_points(&[0, 0, 5, 0], 0.0, 0.0, 30.0, 10.0).len(),
+            1
+        );
+        assert!(rank_history_points(&[0, 0], 0.0, 0.0, 30.0, 10.0).is_empty());
+    }
+}
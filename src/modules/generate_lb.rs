@@ -1,18 +1,24 @@
-use super::osu_api::{get_legacy_score_only_status, Beatmap, Score};
+use super::mods::{ModType, Mods};
+use super::osu_api::{Beatmap, GameMode, Score, ScoringMode, Statistics};
 use skia_safe::{
     surfaces, BlendMode, Canvas, Color, Data, EncodedImageFormat, Font, FontMgr, Image, Matrix,
-    Paint, Path, Point, Rect,
+    Paint, Path, Point, Rect, Shader, TileMode,
 };
 
 // Constants for layout
-const PADDING: f32 = 15.0;
-const CANVAS_WIDTH: f32 = 600.0;
-const CELL_HEIGHT: f32 = 90.0;
+pub const PADDING: f32 = 15.0;
+pub const CANVAS_WIDTH: f32 = 600.0;
+pub const CELL_HEIGHT: f32 = 90.0;
+pub const HEADER_HEIGHT: f32 = 40.0;
+
+const BADGE_HEIGHT: f32 = 20.0;
+const BADGE_PADDING: f32 = 7.0;
+const BADGE_SPACING: f32 = 4.0;
 
 const INTER_FONT: &[u8] = include_bytes!(".././fonts/Inter_18pt-Regular.ttf");
 const INTER_FONT_BOLD: &[u8] = include_bytes!(".././fonts/Inter_18pt-Bold.ttf");
 
-fn calc_text_width(font: &Font, text: &str) -> f32 {
+pub fn calc_text_width(font: &Font, text: &str) -> f32 {
     let mut paint = Paint::default();
     paint.set_anti_alias(true);
     font.measure_str(text, Some(&paint)).0
@@ -38,7 +44,14 @@ fn create_rounded_rect_path(x: f32, y: f32, width: f32, height: f32, radius: f32
     path
 }
 
-fn draw_profile_image(canvas: &Canvas, avatar: Vec<u8>, x: f32, y: f32, size: f32, radius: f32) {
+pub fn draw_profile_image(
+    canvas: &Canvas,
+    avatar: Vec<u8>,
+    x: f32,
+    y: f32,
+    size: f32,
+    radius: f32,
+) {
     let path = create_rounded_rect_path(x, y, size, size, radius);
 
     let image_data = Data::new_copy(&avatar);
@@ -65,25 +78,115 @@ fn draw_profile_image(canvas: &Canvas, avatar: Vec<u8>, x: f32, y: f32, size: f3
 
             canvas.restore();
         }
+    } else {
+        draw_default_avatar(canvas, &path, x, y, size);
     }
 }
 
-fn draw_username(canvas: &Canvas, font: &Font, username: &str, x: f32, y: f32) {
+// Grey silhouette for avatars that couldn't be loaded
+fn draw_default_avatar(canvas: &Canvas, path: &Path, x: f32, y: f32, size: f32) {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgb(58, 58, 58)); // #3a3a3a
+    paint.set_anti_alias(true);
+    canvas.draw_path(path, &paint);
+
+    paint.set_color(Color::from_rgb(120, 120, 120)); // #787878
+    canvas.save();
+    canvas.clip_path(path, None, Some(true));
+    canvas.draw_circle((x + size / 2.0, y + size * 0.4), size * 0.18, &paint);
+    canvas.draw_circle((x + size / 2.0, y + size * 1.05), size * 0.38, &paint);
+    canvas.restore();
+}
+
+pub fn draw_header(canvas: &Canvas, font: &Font, title: &str, subtitle: &str) {
     let mut paint = Paint::default();
     paint.set_color(Color::WHITE);
     paint.set_anti_alias(true);
 
-    canvas.draw_str(username, (x, y), font, &paint);
+    let y = PADDING + HEADER_HEIGHT / 2.0;
+    canvas.draw_str(title, (PADDING, y), font, &paint);
+
+    paint.set_color(Color::from_rgb(170, 170, 170)); // #aaaaaa
+    let subtitle_width = calc_text_width(font, subtitle);
+    canvas.draw_str(
+        subtitle,
+        (CANVAS_WIDTH - PADDING - subtitle_width, y),
+        font,
+        &paint,
+    );
+}
+
+// Returns the width of the drawn position so the username can follow it
+pub fn draw_position(canvas: &Canvas, font: &Font, position: usize, x: f32, y: f32) -> f32 {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgb(170, 170, 170)); // #aaaaaa
+    paint.set_anti_alias(true);
+
+    let text = format!("#{}", position);
+    canvas.draw_str(&text, (x, y), font, &paint);
+
+    calc_text_width(font, &text)
 }
 
-fn draw_mods(canvas: &Canvas, font: &Font, mods: &str, x: f32, y: f32) {
+fn draw_username(canvas: &Canvas, font: &Font, username: &str, x: f32, y: f32) {
     let mut paint = Paint::default();
     paint.set_color(Color::WHITE);
     paint.set_anti_alias(true);
 
-    let text_width = calc_text_width(font, mods);
+    canvas.draw_str(username, (x, y), font, &paint);
+}
+
+// Colours of the mod select sections in osu!lazer
+fn mod_type_color(mod_type: ModType) -> Color {
+    match mod_type {
+        ModType::DifficultyReduction => Color::from_rgb(178, 255, 102), // #b2ff66
+        ModType::DifficultyIncrease => Color::from_rgb(255, 102, 102),  // #ff6666
+        ModType::Automation => Color::from_rgb(102, 204, 255),          // #66ccff
+        ModType::Conversion => Color::from_rgb(140, 102, 255),          // #8c66ff
+        ModType::Fun => Color::from_rgb(255, 102, 171),                 // #ff66ab
+        ModType::System => Color::from_rgb(255, 221, 85),               // #ffdd55
+    }
+}
 
-    canvas.draw_str(mods, (x - text_width, y), font, &paint);
+fn mod_badge_width(font: &Font, label: &str) -> f32 {
+    calc_text_width(font, label) + BADGE_PADDING * 2.0
+}
+
+pub fn mod_badges_width(font: &Font, mods: &Mods) -> f32 {
+    let widths: Vec<f32> = mods
+        .iter()
+        .map(|m| mod_badge_width(font, &m.label()))
+        .collect();
+    widths.iter().sum::<f32>() + BADGE_SPACING * widths.len().saturating_sub(1) as f32
+}
+
+// One pill per mod starting at `x`, with `y` as the text baseline like for other text
+pub fn draw_mod_badges(canvas: &Canvas, font: &Font, mods: &Mods, x: f32, y: f32) {
+    let mut badge_paint = Paint::default();
+    badge_paint.set_anti_alias(true);
+
+    let mut text_paint = Paint::default();
+    text_paint.set_color(Color::from_rgb(34, 34, 34)); // #222222
+    text_paint.set_anti_alias(true);
+
+    let mut badge_x = x;
+    for m in mods.iter() {
+        let label = m.label();
+        let width = mod_badge_width(font, &label);
+
+        badge_paint.set_color(mod_type_color(m.mod_type()));
+        let path = create_rounded_rect_path(
+            badge_x,
+            y - BADGE_HEIGHT + 5.0,
+            width,
+            BADGE_HEIGHT,
+            BADGE_HEIGHT / 2.0,
+        );
+        canvas.draw_path(&path, &badge_paint);
+        canvas.draw_str(&label, (badge_x + BADGE_PADDING, y), font, &text_paint);
+
+        badge_x += width + BADGE_SPACING;
+    }
 }
 
 fn draw_score_with_combo(
@@ -91,7 +194,7 @@ fn draw_score_with_combo(
     font: &Font,
     score: i64,
     combo: i32,
-    max_combo: &str,
+    max_combo: i32,
     x: f32,
     y: f32,
 ) {
@@ -99,7 +202,13 @@ fn draw_score_with_combo(
     paint.set_color(Color::WHITE);
     paint.set_anti_alias(true);
 
-    let score_formatted = score
+    let text = format!("{} ({}x / {}x)", format_number(score), combo, max_combo);
+    canvas.draw_str(&text, (x, y), font, &paint);
+}
+
+// 1234567 -> "1,234,567"
+pub fn format_number(number: i64) -> String {
+    number
         .to_string()
         .as_bytes()
         .rchunks(3)
@@ -107,10 +216,7 @@ fn draw_score_with_combo(
         .map(std::str::from_utf8)
         .collect::<Result<Vec<&str>, _>>()
         .unwrap()
-        .join(",");
-
-    let text = format!("{} ({}x / {}x)", score_formatted, combo, max_combo);
-    canvas.draw_str(&text, (x, y), font, &paint);
+        .join(",")
 }
 
 fn draw_accuracy(canvas: &Canvas, font: &Font, accuracy: f32, x: f32, y: f32) {
@@ -125,30 +231,44 @@ fn draw_accuracy(canvas: &Canvas, font: &Font, accuracy: f32, x: f32, y: f32) {
     canvas.draw_str(&text, (x - text_width, y), font, &paint);
 }
 
+fn draw_pp(canvas: &Canvas, font: &Font, pp: f64, x: f32, y: f32) {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgb(255, 102, 171)); // #ff66ab
+    paint.set_anti_alias(true);
+
+    let text = format!("{:.0}pp", pp);
+
+    let text_width = calc_text_width(font, &text);
+
+    canvas.draw_str(&text, (x - text_width, y), font, &paint);
+}
+
 fn draw_ended_at_date(canvas: &Canvas, font: &Font, ended_at: &str, x: f32, y: f32) {
     let mut paint = Paint::default();
     paint.set_color(Color::WHITE);
     paint.set_anti_alias(true);
 
-    // Parse and format date
-    let formatted_date = {
-        let date = ended_at.split('T').next().unwrap_or("");
-        let parts: Vec<&str> = date.split('-').collect();
-        if parts.len() == 3 {
-            let year = parts[0];
-            let month = parts[1].trim_start_matches('0');
-            let day = parts[2].trim_start_matches('0');
-            format!("{}. {}. {}", day, month, year)
-        } else {
-            ended_at.to_string()
-        }
-    };
+    let formatted_date = format_date(ended_at);
 
     let text_width = calc_text_width(font, &formatted_date);
     canvas.draw_str(&formatted_date, (x - text_width, y), font, &paint);
 }
 
-fn draw_rank(canvas: &Canvas, font: &Font, rank: &str, x: f32, y: f32) {
+// "2024-05-01T12:00:00Z" -> "1. 5. 2024"
+pub fn format_date(ended_at: &str) -> String {
+    let date = ended_at.split('T').next().unwrap_or("");
+    let parts: Vec<&str> = date.split('-').collect();
+    if parts.len() == 3 {
+        let year = parts[0];
+        let month = parts[1].trim_start_matches('0');
+        let day = parts[2].trim_start_matches('0');
+        format!("{}. {}. {}", day, month, year)
+    } else {
+        ended_at.to_string()
+    }
+}
+
+pub fn draw_rank(canvas: &Canvas, font: &Font, rank: &str, x: f32, y: f32) {
     let (rank_to_display, rank_color) = match rank {
         "XH" => ("SS", Color::from_rgb(239, 239, 239)), // #efefef
         "X" => ("SS", Color::from_rgb(255, 226, 76)),   // #ffe24c
@@ -158,6 +278,7 @@ fn draw_rank(canvas: &Canvas, font: &Font, rank: &str, x: f32, y: f32) {
         "B" => ("B", Color::from_rgb(57, 143, 230)),    // #398fe6
         "C" => ("C", Color::from_rgb(186, 57, 230)),    // #ba39e6
         "D" => ("D", Color::from_rgb(230, 57, 57)),     // #e63939
+        "F" => ("F", Color::from_rgb(230, 57, 57)),     // #e63939
         _ => ("?", Color::from_rgb(230, 57, 57)),       // Default red
     };
 
@@ -165,12 +286,43 @@ fn draw_rank(canvas: &Canvas, font: &Font, rank: &str, x: f32, y: f32) {
     paint.set_color(rank_color);
     paint.set_anti_alias(true);
 
-    canvas.draw_str(rank_to_display, (x, y), &font, &paint);
+    canvas.draw_str(rank_to_display, (x, y), font, &paint);
 }
 
-fn draw_statistics(canvas: &Canvas, font: &Font, statistics: (i32, i32, i32), x: f32, y: f32) {
-    let (ok, meh, miss) = statistics;
+pub fn get_judgements(statistics: &Statistics, mode: GameMode) -> Vec<(i32, Color)> {
+    let count = |value: Option<i32>| value.unwrap_or(0);
+
+    match mode {
+        GameMode::Osu => vec![
+            (count(statistics.ok), Color::GREEN),
+            (count(statistics.meh), Color::YELLOW),
+            (count(statistics.miss), Color::RED),
+        ],
+        GameMode::Taiko => vec![
+            (count(statistics.ok), Color::GREEN),
+            (count(statistics.miss), Color::RED),
+        ],
+        GameMode::Fruits => vec![
+            (count(statistics.large_tick_hit), Color::GREEN),
+            (count(statistics.small_tick_hit), Color::YELLOW),
+            (
+                count(statistics.small_tick_miss),
+                Color::from_rgb(186, 57, 230),
+            ), // #ba39e6
+            (count(statistics.miss), Color::RED),
+        ],
+        GameMode::Mania => vec![
+            (count(statistics.perfect), Color::from_rgb(165, 230, 255)), // #a5e6ff
+            (count(statistics.great), Color::from_rgb(255, 226, 76)),    // #ffe24c
+            (count(statistics.good), Color::from_rgb(115, 229, 57)),     // #73e539
+            (count(statistics.ok), Color::from_rgb(57, 143, 230)),       // #398fe6
+            (count(statistics.meh), Color::from_rgb(186, 57, 230)),      // #ba39e6
+            (count(statistics.miss), Color::RED),
+        ],
+    }
+}
 
+pub fn draw_statistics(canvas: &Canvas, font: &Font, judgements: &[(i32, Color)], x: f32, y: f32) {
     // Draw numbers
     let mut paint = Paint::default();
     paint.set_color(Color::WHITE);
@@ -179,34 +331,25 @@ fn draw_statistics(canvas: &Canvas, font: &Font, statistics: (i32, i32, i32), x:
     let mut dot_paint = Paint::default();
     dot_paint.set_anti_alias(true);
 
-    // First group (green)
-    let x1 = x - 40.0;
-    let ok_str = ok.to_string();
-    let ok_width = calc_text_width(font, &ok_str);
-    canvas.draw_str(&ok_str, (x1 - ok_width - 10.0, y), &font, &paint);
-    dot_paint.set_color(Color::GREEN);
-    canvas.draw_circle((x1, y - 4.2), 4.0, &dot_paint);
-
-    // Second group (yellow)
-    let x2 = x + 10.0;
-    let meh_str = meh.to_string();
-    let meh_width = calc_text_width(font, &meh_str);
-    canvas.draw_str(&meh_str, (x2 - meh_width - 10.0, y), &font, &paint);
-    dot_paint.set_color(Color::YELLOW);
-    canvas.draw_circle((x2, y - 4.2), 4.0, &dot_paint);
-
-    // Third group (red)
-    let x3 = x + 60.0;
-    let miss_str = miss.to_string();
-    let miss_width = calc_text_width(font, &miss_str);
-    canvas.draw_str(&miss_str, (x3 - miss_width - 10.0, y), &font, &paint);
-    dot_paint.set_color(Color::RED);
-    canvas.draw_circle((x3, y - 4.2), 4.0, &dot_paint);
+    // Groups are 50px apart and centered around x
+    let first_x = x + 10.0 - (judgements.len() as f32 - 1.0) * 25.0;
+
+    for (i, (count, color)) in judgements.iter().enumerate() {
+        let dot_x = first_x + i as f32 * 50.0;
+        let count_str = count.to_string();
+        let count_width = calc_text_width(font, &count_str);
+        canvas.draw_str(&count_str, (dot_x - count_width - 10.0, y), font, &paint);
+        dot_paint.set_color(*color);
+        canvas.draw_circle((dot_x, y - 4.2), 4.0, &dot_paint);
+    }
 }
 
-fn draw_background(canvas: &Canvas, cover_bytes: &Vec<u8>, canvas_width: f32, canvas_height: f32) {
-    let image = Image::from_encoded(Data::new_copy(&cover_bytes))
-        .expect("Failed to create image from cover");
+// Falls back to a gradient when the cover is missing or can't be decoded
+pub fn draw_background(canvas: &Canvas, cover_bytes: &[u8], canvas_width: f32, canvas_height: f32) {
+    let Some(image) = Image::from_encoded(Data::new_copy(cover_bytes)) else {
+        draw_gradient_background(canvas, canvas_width, canvas_height);
+        return;
+    };
 
     let scale_x = canvas_width / image.width() as f32;
     let scale_y = canvas_height / image.height() as f32;
@@ -237,13 +380,82 @@ fn draw_background(canvas: &Canvas, cover_bytes: &Vec<u8>, canvas_width: f32, ca
     }
 }
 
-pub fn generate_leaderboard(
-    leaderboard: Vec<Score>,
-    avatars: Vec<Vec<u8>>,
-    beatmap_info: &Beatmap,
-) -> Vec<u8> {
-    let canvas_height = PADDING + CELL_HEIGHT * leaderboard.len() as f32;
+fn draw_gradient_background(canvas: &Canvas, canvas_width: f32, canvas_height: f32) {
+    let colors = [
+        Color::from_rgb(255, 102, 171), // #ff66ab
+        Color::from_rgb(140, 102, 255), // #8c66ff
+    ];
+    let shader = Shader::linear_gradient(
+        ((0.0, 0.0), (canvas_width, canvas_height)),
+        colors.as_slice(),
+        None,
+        TileMode::Clamp,
+        None,
+        None,
+    );
+
+    if let Some(shader) = shader {
+        let mut paint = Paint::default();
+        paint.set_shader(shader);
+        paint.set_alpha_f(0.2);
+        canvas.draw_rect(
+            Rect::from_xywh(0.0, 0.0, canvas_width, canvas_height),
+            &paint,
+        );
+    }
+}
 
+fn draw_highlight(canvas: &Canvas, y: f32) {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_argb(56, 255, 102, 171)); // #ff66ab
+    paint.set_anti_alias(true);
+
+    let path = create_rounded_rect_path(
+        PADDING / 2.0,
+        y - PADDING / 3.0,
+        CANVAS_WIDTH - PADDING,
+        CELL_HEIGHT - PADDING / 3.0,
+        12.0,
+    );
+    canvas.draw_path(&path, &paint);
+}
+
+fn draw_separator(canvas: &Canvas, y: f32) {
+    let mut paint = Paint::default();
+    paint.set_color(Color::from_rgb(90, 90, 90)); // #5a5a5a
+    paint.set_anti_alias(true);
+    paint.set_stroke_width(1.0);
+
+    canvas.draw_line((PADDING, y), (CANVAS_WIDTH - PADDING, y), &paint);
+}
+
+pub struct LeaderboardPage<'a> {
+    pub title: &'a str,
+    // Star rating with the mods it was calculated for, e.g. "★5.21 HDDT"
+    pub stars: &'a str,
+    pub mode: GameMode,
+    pub scoring: ScoringMode,
+}
+
+pub struct LeaderboardRow<'a> {
+    pub score: &'a Score,
+    pub avatar: Vec<u8>,
+    pub pp: Option<f64>,
+    // Position on the whole leaderboard, not just this page. None for the scores of a
+    // single player, which aren't ranked against each other.
+    pub position: Option<usize>,
+    pub highlighted: bool,
+}
+
+pub struct Fonts {
+    pub default: Font,
+    pub bold: Font,
+    pub smaller: Font,
+    pub large: Font,
+    pub badge: Font,
+}
+
+pub fn load_fonts() -> Fonts {
     let font_mgr = FontMgr::new();
 
     let typeface_default = font_mgr
@@ -253,95 +465,166 @@ pub fn generate_leaderboard(
         .new_from_data(INTER_FONT_BOLD, None)
         .expect("Failed to load Inter Font Bold");
 
-    let default_font = Font::from_typeface(&typeface_default, 18.0);
-    let bold_font = Font::from_typeface(&typeface_bold, 18.0);
-    let smaller_font = Font::from_typeface(&typeface_default, 14.0);
-    let mut surface = surfaces::raster_n32_premul((CANVAS_WIDTH as i32, canvas_height as i32))
-        .expect("Failed to create surface");
+    Fonts {
+        default: Font::from_typeface(&typeface_default, 18.0),
+        bold: Font::from_typeface(&typeface_bold, 18.0),
+        smaller: Font::from_typeface(&typeface_default, 14.0),
+        large: Font::from_typeface(&typeface_bold, 44.0),
+        badge: Font::from_typeface(&typeface_bold, 13.0),
+    }
+}
 
-    let mut canvas = surface.canvas();
-    canvas.clear(Color::BLACK);
+fn draw_row(
+    canvas: &Canvas,
+    fonts: &Fonts,
+    row: &LeaderboardRow,
+    row_y: f32,
+    beatmap_info: &Beatmap,
+    page: &LeaderboardPage,
+) {
+    let score = row.score;
 
-    draw_background(canvas, &beatmap_info.cover, CANVAS_WIDTH, canvas_height);
+    if row.highlighted {
+        draw_highlight(canvas, row_y);
+    }
 
-    let use_legacy_score = get_legacy_score_only_status();
-    leaderboard.iter().enumerate().for_each(|(i, score)| {
-        let row_y = PADDING + i as f32 * CELL_HEIGHT;
-        let score_to_show = if use_legacy_score {
-            score.legacy_total_score
-        } else {
-            score.classic_total_score
-        };
-
-        draw_profile_image(&mut canvas, avatars[i].clone(), PADDING, row_y, 70.0, 10.0);
-
-        draw_username(
-            &mut canvas,
-            &bold_font,
-            &score.user.username,
-            PADDING + 80.0,
-            row_y + CELL_HEIGHT * 0.2,
-        );
+    draw_profile_image(canvas, row.avatar.clone(), PADDING, row_y, 70.0, 10.0);
+
+    let position_width = match row.position {
+        Some(position) => {
+            draw_position(
+                canvas,
+                &fonts.bold,
+                position,
+                PADDING + 80.0,
+                row_y + CELL_HEIGHT * 0.2,
+            ) + 6.0
+        }
+        None => 0.0,
+    };
 
-        draw_mods(
-            &mut canvas,
-            &default_font,
-            &score
-                .mods
-                .iter()
-                .map(|m| m.acronym.clone())
-                .collect::<String>(),
-            CANVAS_WIDTH - PADDING,
-            row_y + CELL_HEIGHT * 0.2,
-        );
+    draw_username(
+        canvas,
+        &fonts.bold,
+        &score.user.username,
+        PADDING + 80.0 + position_width,
+        row_y + CELL_HEIGHT * 0.2,
+    );
 
-        draw_score_with_combo(
-            &mut canvas,
-            &default_font,
-            score_to_show,
-            score.max_combo,
-            &beatmap_info.max_combo,
-            PADDING + 80.0,
-            row_y + CELL_HEIGHT / 2.0,
-        );
+    let mods = Mods::from_score(&score.mods);
+    draw_mod_badges(
+        canvas,
+        &fonts.badge,
+        &mods,
+        CANVAS_WIDTH - PADDING - mod_badges_width(&fonts.badge, &mods),
+        row_y + CELL_HEIGHT * 0.2,
+    );
+
+    draw_score_with_combo(
+        canvas,
+        &fonts.default,
+        score.total_score(page.scoring),
+        score.max_combo,
+        beatmap_info.max_combo,
+        PADDING + 80.0,
+        row_y + CELL_HEIGHT / 2.0,
+    );
+
+    draw_accuracy(
+        canvas,
+        &fonts.default,
+        score.accuracy,
+        CANVAS_WIDTH - PADDING,
+        row_y + CELL_HEIGHT / 2.0,
+    );
 
-        draw_accuracy(
-            &mut canvas,
-            &default_font,
-            score.accuracy,
-            CANVAS_WIDTH - PADDING,
+    if let Some(pp) = row.pp {
+        draw_pp(
+            canvas,
+            &fonts.bold,
+            pp,
+            CANVAS_WIDTH - PADDING - 85.0,
             row_y + CELL_HEIGHT / 2.0,
         );
+    }
 
-        draw_ended_at_date(
-            &mut canvas,
-            &default_font,
-            &score.ended_at,
-            CANVAS_WIDTH - PADDING,
-            row_y + CELL_HEIGHT * 0.75,
-        );
+    draw_ended_at_date(
+        canvas,
+        &fonts.default,
+        &score.ended_at,
+        CANVAS_WIDTH - PADDING,
+        row_y + CELL_HEIGHT * 0.75,
+    );
 
-        draw_rank(
-            &mut canvas,
-            &bold_font,
-            &score.rank,
-            PADDING + 80.0,
-            row_y + CELL_HEIGHT * 0.75,
-        );
+    draw_rank(
+        canvas,
+        &fonts.bold,
+        &score.rank,
+        PADDING + 80.0,
+        row_y + CELL_HEIGHT * 0.75,
+    );
 
-        draw_statistics(
-            &mut canvas,
-            &smaller_font,
-            (
-                score.statistics.ok.unwrap_or(0),
-                score.statistics.meh.unwrap_or(0),
-                score.statistics.miss.unwrap_or(0),
-            ),
-            CANVAS_WIDTH / 2.0,
-            row_y + CELL_HEIGHT * 0.75,
-        );
+    draw_statistics(
+        canvas,
+        &fonts.smaller,
+        &get_judgements(&score.statistics, page.mode),
+        CANVAS_WIDTH / 2.0,
+        row_y + CELL_HEIGHT * 0.75,
+    );
+}
+
+// `pinned` is drawn below a separator after the other rows, used for the requester's
+// score when it isn't on the current page
+pub fn generate_leaderboard(
+    rows: &[LeaderboardRow],
+    pinned: Option<&LeaderboardRow>,
+    beatmap_info: &Beatmap,
+    page: &LeaderboardPage,
+) -> Vec<u8> {
+    let (mode, scoring) = (page.mode, page.scoring);
+
+    let rows_height = CELL_HEIGHT * rows.len() as f32;
+    let pinned_height = pinned.map_or(0.0, |_| PADDING + CELL_HEIGHT);
+    let canvas_height = PADDING + HEADER_HEIGHT + rows_height + pinned_height;
+
+    let fonts = load_fonts();
+    let mut surface = surfaces::raster_n32_premul((CANVAS_WIDTH as i32, canvas_height as i32))
+        .expect("Failed to create surface");
+
+    let canvas = surface.canvas();
+    canvas.clear(Color::BLACK);
+
+    draw_background(canvas, &beatmap_info.cover, CANVAS_WIDTH, canvas_height);
+
+    let scoring_name = match scoring {
+        ScoringMode::Legacy => "Legacy scoring",
+        ScoringMode::Lazer => "Lazer scoring",
+    };
+    draw_header(
+        canvas,
+        &fonts.bold,
+        page.title,
+        &format!("{} • {} • {}", page.stars, mode.name(), scoring_name),
+    );
+
+    rows.iter().enumerate().for_each(|(i, row)| {
+        let row_y = PADDING + HEADER_HEIGHT + i as f32 * CELL_HEIGHT;
+        draw_row(canvas, &fonts, row, row_y, beatmap_info, page);
     });
 
+    if let Some(row) = pinned {
+        let separator_y = PADDING + HEADER_HEIGHT + rows_height;
+        draw_separator(canvas, separator_y);
+        draw_row(
+            canvas,
+            &fonts,
+            row,
+            separator_y + PADDING,
+            beatmap_info,
+            page,
+        );
+    }
+
     // Save the output as bytes vector
     let image = surface.image_snapshot();
     let data = image
@@ -0,0 +1,100 @@
+use super::commands::{LeaderboardOptions, LeaderboardType};
+use super::mods::ModsFilter;
+use super::osu_api::{Beatmap, GameMode, Score, ScoringMode};
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TTL_SECONDS: u64 = 60;
+const MAX_ENTRIES: usize = 100;
+
+// Set LEADERBOARD_CACHE_SECONDS=0 to always ask osu!
+static TTL: Lazy<Duration> = Lazy::new(|| {
+    let seconds = env::var("LEADERBOARD_CACHE_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_TTL_SECONDS);
+    Duration::from_secs(seconds)
+});
+
+// Everything that changes which scores osu! returns, mods and pages are applied afterwards
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct LeaderboardKey {
+    beatmap_id: String,
+    mode: Option<GameMode>,
+    board: String,
+    scoring: ScoringMode,
+}
+
+impl LeaderboardKey {
+    pub fn new(beatmap_id: &str, options: &LeaderboardOptions) -> Self {
+        let board = match &options.board {
+            LeaderboardType::Global => "global".to_string(),
+            LeaderboardType::Country => "country".to_string(),
+            LeaderboardType::Friends => "friends".to_string(),
+            LeaderboardType::CountryCode(code) => format!("country:{}", code),
+            // With a mods filter every score of the members is fetched, not only the best
+            LeaderboardType::Server(osu_ids) => match options.mods {
+                ModsFilter::Any => format!("server:{:?}", osu_ids),
+                _ => format!("server-all:{:?}", osu_ids),
+            },
+            LeaderboardType::Player(user) => format!("player:{}", user.id),
+        };
+
+        Self {
+            beatmap_id: beatmap_id.to_string(),
+            mode: options.mode,
+            board,
+            scoring: options.scoring,
+        }
+    }
+}
+
+pub struct CachedLeaderboard {
+    pub beatmap: Beatmap,
+    pub scores: Vec<Score>,
+    pub fetched_at: Instant,
+}
+
+static LEADERBOARDS: Lazy<Mutex<HashMap<LeaderboardKey, Arc<CachedLeaderboard>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn get(key: &LeaderboardKey) -> Option<Arc<CachedLeaderboard>> {
+    LEADERBOARDS
+        .lock()
+        .unwrap()
+        .get(key)
+        .filter(|l| l.fetched_at.elapsed() < *TTL)
+        .cloned()
+}
+
+pub fn insert(key: LeaderboardKey, beatmap: Beatmap, scores: Vec<Score>) {
+    if TTL.is_zero() {
+        return;
+    }
+
+    let mut leaderboards = LEADERBOARDS.lock().unwrap();
+    leaderboards.retain(|_, l| l.fetched_at.elapsed() < *TTL);
+
+    if leaderboards.len() >= MAX_ENTRIES {
+        let oldest = leaderboards
+            .iter()
+            .min_by_key(|(_, l)| l.fetched_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            leaderboards.remove(&oldest);
+        }
+    }
+
+    leaderboards.insert(
+        key,
+        Arc::new(CachedLeaderboard {
+            beatmap,
+            scores,
+            fetched_at: Instant::now(),
+        }),
+    );
+}
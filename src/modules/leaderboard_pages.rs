@@ -0,0 +1,117 @@
+use super::commands::LeaderboardOptions;
+use super::osu_api::{Beatmap, GameMode, Score};
+use super::performance::{AdjustedStats, PerformanceCalculator};
+
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const PAGE_SIZE: usize = 7;
+
+// Page buttons stop working once their leaderboard is dropped from memory
+const SESSION_TTL: Duration = Duration::from_secs(15 * 60);
+const MAX_SESSIONS: usize = 50;
+
+// Everything needed to render any page of a leaderboard without asking osu! again
+pub struct LeaderboardSession {
+    pub beatmap: Beatmap,
+    pub mode: GameMode,
+    pub options: LeaderboardOptions,
+    pub scores: Vec<Score>,
+    pub calculator: Option<PerformanceCalculator>,
+    // Beatmap stats with the mods of the filter, None without a calculator
+    pub stats: Option<AdjustedStats>,
+    pub requester_osu_id: Option<i64>,
+    // When osu! returned the scores, earlier than the session for cached leaderboards
+    pub fetched_at: Instant,
+    created_at: Instant,
+}
+
+impl LeaderboardSession {
+    pub fn new(
+        beatmap: Beatmap,
+        mode: GameMode,
+        options: LeaderboardOptions,
+        scores: Vec<Score>,
+        calculator: Option<PerformanceCalculator>,
+        stats: Option<AdjustedStats>,
+        requester_osu_id: Option<i64>,
+    ) -> Self {
+        Self {
+            beatmap,
+            mode,
+            options,
+            scores,
+            calculator,
+            stats,
+            requester_osu_id,
+            fetched_at: Instant::now(),
+            created_at: Instant::now(),
+        }
+    }
+
+    pub fn requester_index(&self) -> Option<usize> {
+        let osu_id = self.requester_osu_id?;
+        self.scores.iter().position(|s| s.user.id == osu_id)
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.scores.len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    // Matches an osu! user ID or a username like osu! does, ignoring case and treating
+    // underscores as spaces
+    pub fn find_player_page(&self, player: &str) -> Option<usize> {
+        let normalize = |name: &str| name.trim().replace('_', " ").to_lowercase();
+        let player = normalize(player);
+
+        self.scores
+            .iter()
+            .position(|s| normalize(&s.user.username) == player || s.user.id.to_string() == player)
+            .map(|i| i / PAGE_SIZE + 1)
+    }
+}
+
+static SESSIONS: Lazy<Mutex<HashMap<u64, Arc<LeaderboardSession>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+// Starting at the boot time in milliseconds keeps IDs from before a restart from being
+// reused, so their buttons report the leaderboard as expired instead of showing another one
+static NEXT_SESSION_ID: Lazy<AtomicU64> = Lazy::new(|| {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(1);
+    AtomicU64::new(now)
+});
+
+pub fn store_session(session: LeaderboardSession) -> (u64, Arc<LeaderboardSession>) {
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+    let session = Arc::new(session);
+
+    let mut sessions = SESSIONS.lock().unwrap();
+    sessions.retain(|_, s| s.created_at.elapsed() < SESSION_TTL);
+
+    if sessions.len() >= MAX_SESSIONS {
+        let oldest = sessions
+            .iter()
+            .min_by_key(|(_, s)| s.created_at)
+            .map(|(id, _)| *id);
+        if let Some(oldest) = oldest {
+            sessions.remove(&oldest);
+        }
+    }
+
+    sessions.insert(id, session.clone());
+    (id, session)
+}
+
+pub fn get_session(id: u64) -> Option<Arc<LeaderboardSession>> {
+    SESSIONS
+        .lock()
+        .unwrap()
+        .get(&id)
+        .filter(|s| s.created_at.elapsed() < SESSION_TTL)
+        .cloned()
+}
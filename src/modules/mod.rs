@@ -1,3 +1,16 @@
+pub mod asset_cache;
+pub mod beatmap_ref;
+pub mod channel_context;
+pub mod commands;
+pub mod country_tracker;
+pub mod data_source;
 pub mod database;
+pub mod generate_card;
 pub mod generate_lb;
+pub mod leaderboard_cache;
+pub mod leaderboard_pages;
+pub mod mods;
 pub mod osu_api;
+pub mod performance;
+pub mod rate_limiter;
+pub mod slash_commands;
@@ -0,0 +1,467 @@
+use super::osu_api::{Mod, ModSettings};
+
+use std::fmt;
+
+// Every acronym osu! uses across stable and lazer, grouped like in the game's mod select
+// https://osu.ppy.sh/wiki/en/Gameplay/Game_modifier
+const KNOWN_MODS: [&str; 54] = [
+    "EZ", "NF", "HT", "DC", "NR", "HR", "SD", "PF", "DT", "NC", "HD", "FI", "FL", "BL", "ST", "AC",
+    "AT", "CN", "RX", "AP", "SO", "TP", "DA", "CL", "RD", "MR", "AL", "SG", "IN", "HO", "CS", "1K",
+    "2K", "3K", "4K", "5K", "6K", "7K", "8K", "9K", "TR", "WG", "SI", "GR", "DF", "WU", "WD", "BR",
+    "AD", "MU", "NS", "MG", "RP", "TD",
+];
+
+// Legacy mod bits as used by rosu-pp, see https://github.com/ppy/osu-api/wiki#mods
+const MOD_BITS: [(&str, u32); 22] = [
+    ("NF", 1),
+    ("EZ", 2),
+    ("TD", 4),
+    ("HD", 8),
+    ("HR", 16),
+    ("SD", 32),
+    ("DT", 64),
+    ("RX", 128),
+    ("HT", 256),
+    ("NC", 576),
+    ("FL", 1024),
+    ("SO", 4096),
+    ("AP", 8192),
+    ("PF", 16416),
+    ("4K", 32768),
+    ("5K", 65536),
+    ("6K", 131072),
+    ("7K", 262144),
+    ("8K", 524288),
+    ("FI", 1048576),
+    ("9K", 16777216),
+    ("MR", 1073741824),
+];
+
+// Sections of the mod select, which also decide the colour of a mod's icon
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModType {
+    DifficultyReduction,
+    DifficultyIncrease,
+    Automation,
+    Conversion,
+    Fun,
+    System,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameMod {
+    pub acronym: String,
+    // Custom rates and difficulty adjust values of lazer scores
+    pub settings: ModSettings,
+}
+
+impl GameMod {
+    pub fn mod_type(&self) -> ModType {
+        match self.acronym.as_str() {
+            "EZ" | "NF" | "HT" | "DC" | "NR" => ModType::DifficultyReduction,
+            "HR" | "SD" | "PF" | "DT" | "NC" | "HD" | "FI" | "FL" | "BL" | "ST" | "AC" => {
+                ModType::DifficultyIncrease
+            }
+            "AT" | "CN" | "RX" | "AP" | "SO" => ModType::Automation,
+            "TD" => ModType::System,
+            acronym if acronym.ends_with('K') => ModType::Conversion,
+            "TP" | "DA" | "CL" | "RD" | "MR" | "AL" | "SG" | "IN" | "HO" | "CS" => {
+                ModType::Conversion
+            }
+            _ => ModType::Fun,
+        }
+    }
+
+    // e.g. "DT 1.25x" or "DA CS4 AR10.5", just the acronym with default settings
+    pub fn label(&self) -> String {
+        let settings = &self.settings;
+        let values: Vec<String> = [
+            ("", settings.speed_change, "x"),
+            ("CS", settings.circle_size, ""),
+            ("AR", settings.approach_rate, ""),
+            ("OD", settings.overall_difficulty, ""),
+            ("HP", settings.drain_rate, ""),
+        ]
+        .iter()
+        .filter_map(|(name, value, suffix)| {
+            value.map(|v| format!("{}{}{}", name, (v * 100.0).round() / 100.0, suffix))
+        })
+        .collect();
+
+        match values.is_empty() {
+            true => self.acronym.clone(),
+            false => format!("{} {}", self.acronym, values.join(" ")),
+        }
+    }
+}
+
+// Mods of a score or typed by a user, in the order they were given
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mods {
+    mods: Vec<GameMod>,
+}
+
+impl Mods {
+    // Acronyms like HDDT, case insensitive and with an optional leading +. NM is nomod.
+    // Rate mods can be given a custom rate like lazer's, e.g. DT1.3, DT1.3x or DT(1.3x).
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        Self::parse_acronyms(input.strip_prefix('+').unwrap_or(input))
+    }
+
+    fn parse_acronyms(input: &str) -> Result<Self, String> {
+        let input = input.trim().to_uppercase();
+        if input.is_empty() || input == "NM" {
+            return Ok(Self::default());
+        }
+
+        let invalid = || {
+            format!(
+                "Invalid mods {}. Use two-letter acronyms like HDDT, rates like DT1.3.",
+                input
+            )
+        };
+
+        let chars: Vec<char> = input.chars().collect();
+        let mut mods = Self::default();
+        let mut i = 0;
+        while i < chars.len() {
+            let acronym: String = chars.get(i..i + 2).ok_or_else(invalid)?.iter().collect();
+            if !acronym.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(invalid());
+            }
+            if !KNOWN_MODS.contains(&acronym.as_str()) {
+                return Err(format!("Unknown mod {}", acronym));
+            }
+            i += 2;
+
+            let mut settings = ModSettings::default();
+            if let Some((rate, end)) = parse_rate(&chars, i, &acronym).map_err(|_| invalid())? {
+                settings.speed_change = Some(check_rate(&acronym, rate)?);
+                i = end;
+            }
+            mods.push(acronym, settings);
+        }
+
+        Ok(mods)
+    }
+
+    pub fn from_score(mods: &[Mod]) -> Self {
+        let mut parsed = Self::default();
+        for m in mods {
+            parsed.push(m.acronym.to_uppercase(), m.settings.clone());
+        }
+        parsed
+    }
+
+    fn push(&mut self, acronym: String, settings: ModSettings) {
+        if !self.contains(&acronym) {
+            self.mods.push(GameMod { acronym, settings });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mods.is_empty()
+    }
+
+    pub fn contains(&self, acronym: &str) -> bool {
+        self.mods.iter().any(|m| m.acronym == acronym)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &GameMod> {
+        self.mods.iter()
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.mods
+            .iter()
+            .filter_map(|m| MOD_BITS.iter().find(|(acronym, _)| *acronym == m.acronym))
+            .fold(0, |bits, (_, bit)| bits | bit)
+    }
+
+    // Only set when a lazer score changed the rate from the usual 1.5x or 0.75x
+    pub fn custom_clock_rate(&self) -> Option<f64> {
+        self.mods
+            .iter()
+            .filter(|m| matches!(m.acronym.as_str(), "DT" | "NC" | "HT" | "DC"))
+            .find_map(|m| m.settings.speed_change)
+    }
+
+    // Values set with difficulty adjust, all unset without DA
+    pub fn difficulty_adjust(&self) -> ModSettings {
+        self.mods
+            .iter()
+            .find(|m| m.acronym == "DA")
+            .map(|m| m.settings.clone())
+            .unwrap_or_default()
+    }
+
+    // Set on scores from stable, which are calculated without lazer's slider changes
+    pub fn is_classic(&self) -> bool {
+        self.contains("CL")
+    }
+
+    // NC is DT and DC is HT with a different pitch, PF is SD that fails on any non-300
+    fn equivalent_acronyms(&self) -> Vec<&str> {
+        let mut acronyms: Vec<&str> = self
+            .mods
+            .iter()
+            .map(|m| match m.acronym.as_str() {
+                "NC" => "DT",
+                "DC" => "HT",
+                "PF" => "SD",
+                acronym => acronym,
+            })
+            .collect();
+        acronyms.sort_unstable();
+        acronyms.dedup();
+        acronyms
+    }
+}
+
+// The rate after a rate mod starting at `start` and where it ends, None without one.
+// Digits followed by K are a key mod like 4K instead.
+fn parse_rate(chars: &[char], start: usize, acronym: &str) -> Result<Option<(f64, usize)>, ()> {
+    if !matches!(acronym, "DT" | "NC" | "HT" | "DC") {
+        return Ok(None);
+    }
+
+    let parenthesized = chars.get(start) == Some(&'(');
+    let digits_start = if parenthesized { start + 1 } else { start };
+    let mut end = digits_start;
+    while end < chars.len() && (chars[end].is_ascii_digit() || chars[end] == '.') {
+        end += 1;
+    }
+
+    if end == digits_start || (!parenthesized && chars.get(end) == Some(&'K')) {
+        return match parenthesized {
+            true => Err(()),
+            false => Ok(None),
+        };
+    }
+
+    let rate: String = chars[digits_start..end].iter().collect();
+    let rate = rate.parse::<f64>().map_err(|_| ())?;
+    if chars.get(end) == Some(&'X') {
+        end += 1;
+    }
+    if parenthesized {
+        if chars.get(end) != Some(&')') {
+            return Err(());
+        }
+        end += 1;
+    }
+
+    Ok(Some((rate, end)))
+}
+
+// The rates lazer allows for each rate mod
+fn check_rate(acronym: &str, rate: f64) -> Result<f64, String> {
+    let (min, max) = match acronym {
+        "DT" | "NC" => (1.01, 2.0),
+        _ => (0.5, 0.99),
+    };
+
+    match (min..=max).contains(&rate) {
+        true => Ok(rate),
+        false => Err(format!("{} rates go from {}x to {}x", acronym, min, max)),
+    }
+}
+
+// e.g. HDDT1.3x, empty for nomod
+impl fmt::Display for Mods {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for m in &self.mods {
+            f.write_str(&m.acronym)?;
+            if let Some(rate) = m.settings.speed_change {
+                write!(f, "{}x", (rate * 100.0).round() / 100.0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Which scores to keep, typed as HDHR (exactly), +HD (including) or -EZ (excluding).
+// NM keeps only nomod scores.
+#[derive(Clone, Debug, Default)]
+pub enum ModsFilter {
+    #[default]
+    Any,
+    Exact(Mods),
+    Include(Mods),
+    Exclude(Mods),
+}
+
+impl ModsFilter {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        if input.eq_ignore_ascii_case("NM") {
+            return Ok(Self::Exact(Mods::default()));
+        }
+
+        let (operator, mods) = match input.chars().next() {
+            Some(c @ ('+' | '-')) => (Some(c), Mods::parse_acronyms(&input[1..])?),
+            _ => (None, Mods::parse(input)?),
+        };
+
+        match operator {
+            Some(c) if mods.is_empty() => Err(format!("Add mods after {}, e.g. {}HD", c, c)),
+            Some('+') => Ok(Self::Include(mods)),
+            Some(_) => Ok(Self::Exclude(mods)),
+            None if mods.is_empty() => Ok(Self::Any),
+            None => Ok(Self::Exact(mods)),
+        }
+    }
+
+    // SD and PF don't change how a play is scored, so HDHR also matches HDHRPF. CL is
+    // only compared when the filter asks for it.
+    pub fn matches(&self, mods: &Mods) -> bool {
+        let score_mods = mods.equivalent_acronyms();
+        match self {
+            Self::Any => true,
+            Self::Exact(filter) => {
+                let keep_cl = filter.is_classic();
+                counted_acronyms(filter.equivalent_acronyms(), keep_cl)
+                    == counted_acronyms(score_mods, keep_cl)
+            }
+            Self::Include(filter) => filter
+                .equivalent_acronyms()
+                .iter()
+                .all(|m| score_mods.contains(m)),
+            Self::Exclude(filter) => filter
+                .equivalent_acronyms()
+                .iter()
+                .all(|m| !score_mods.contains(m)),
+        }
+    }
+
+    // The mods star ratings are calculated with, nomod unless scores are known to have them
+    pub fn star_mods(&self) -> Mods {
+        match self {
+            Self::Exact(mods) | Self::Include(mods) => mods.clone(),
+            Self::Any | Self::Exclude(_) => Mods::default(),
+        }
+    }
+}
+
+fn counted_acronyms(acronyms: Vec<&str>, keep_cl: bool) -> Vec<&str> {
+    acronyms
+        .into_iter()
+        .filter(|m| *m != "SD" && (keep_cl || *m != "CL"))
+        .collect()
+}
+
+impl fmt::Display for ModsFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => Ok(()),
+            Self::Exact(mods) if mods.is_empty() => f.write_str("NM"),
+            Self::Exact(mods) => write!(f, "{}", mods),
+            Self::Include(mods) => write!(f, "+{}", mods),
+            Self::Exclude(mods) => write!(f, "-{}", mods),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score_mods(acronyms: &[&str]) -> Mods {
+        let mut mods = Mods::default();
+        for acronym in acronyms {
+            mods.push(acronym.to_string(), ModSettings::default());
+        }
+        mods
+    }
+
+    #[test]
+    fn parses_mods_case_insensitively() {
+        let mods = Mods::parse("+hdDt").unwrap();
+        assert_eq!(mods.to_string(), "HDDT");
+        assert_eq!(mods.bits(), 8 | 64);
+    }
+
+    #[test]
+    fn rejects_invalid_mods() {
+        assert!(Mods::parse("HDD").is_err());
+        assert!(Mods::parse("XX").is_err());
+        assert!(Mods::parse("H!").is_err());
+        assert!(Mods::parse("hé").is_err());
+        assert!(Mods::parse("++HD").is_err());
+        assert!(ModsFilter::parse("++HD").is_err());
+        assert!(ModsFilter::parse("-+HD").is_err());
+    }
+
+    #[test]
+    fn parses_custom_rates() {
+        for input in ["DT1.3", "dt1.3x", "DT(1.3x)", "DT(1.3)", "+HDDT1.3"] {
+            let mods = Mods::parse(input).unwrap();
+            assert_eq!(mods.custom_clock_rate(), Some(1.3), "{}", input);
+            assert!(mods.to_string().ends_with("DT1.3x"), "{}", input);
+        }
+
+        let mods = Mods::parse("HT0.8HD").unwrap();
+        assert_eq!(mods.custom_clock_rate(), Some(0.8));
+        assert_eq!(mods.to_string(), "HT0.8xHD");
+
+        // A key mod after a rate mod isn't a rate
+        let mods = Mods::parse("DT4K").unwrap();
+        assert_eq!(mods.custom_clock_rate(), None);
+        assert!(mods.contains("4K"));
+
+        assert!(Mods::parse("DT3").is_err());
+        assert!(Mods::parse("HT1.2").is_err());
+        assert!(Mods::parse("DT(1.3").is_err());
+        assert!(Mods::parse("DT()").is_err());
+        assert!(Mods::parse("DT1..3").is_err());
+        assert!(Mods::parse("HD1.3").is_err());
+    }
+
+    #[test]
+    fn difficulty_adjust_values_come_from_da() {
+        let mut mods = score_mods(&["HD"]);
+        assert_eq!(mods.difficulty_adjust(), ModSettings::default());
+
+        let settings = ModSettings {
+            approach_rate: Some(10.5),
+            circle_size: Some(4.0),
+            ..ModSettings::default()
+        };
+        mods.push("DA".to_string(), settings.clone());
+        assert_eq!(mods.difficulty_adjust(), settings);
+    }
+
+    #[test]
+    fn nm_is_nomod() {
+        assert!(Mods::parse("NM").unwrap().is_empty());
+
+        let filter = ModsFilter::parse("nm").unwrap();
+        assert!(matches!(&filter, ModsFilter::Exact(mods) if mods.is_empty()));
+        assert_eq!(filter.to_string(), "NM");
+        assert!(filter.matches(&score_mods(&[])));
+        assert!(filter.matches(&score_mods(&["CL"])));
+        assert!(!filter.matches(&score_mods(&["HD"])));
+    }
+
+    #[test]
+    fn exact_filter_treats_equivalent_mods_alike() {
+        let filter = ModsFilter::parse("HDDT").unwrap();
+        assert!(filter.matches(&score_mods(&["HD", "NC"])));
+        assert!(filter.matches(&score_mods(&["DT", "HD", "PF", "CL"])));
+        assert!(!filter.matches(&score_mods(&["HD", "DT", "HR"])));
+    }
+
+    #[test]
+    fn include_and_exclude_filters() {
+        let include = ModsFilter::parse("+HD").unwrap();
+        assert!(include.matches(&score_mods(&["HD", "HR"])));
+        assert!(!include.matches(&score_mods(&["HR"])));
+
+        let exclude = ModsFilter::parse("-EZ").unwrap();
+        assert!(exclude.matches(&score_mods(&["HD"])));
+        assert!(!exclude.matches(&score_mods(&["EZ", "HD"])));
+
+        assert!(ModsFilter::parse("+").is_err());
+    }
+}
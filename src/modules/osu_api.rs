@@ -1,17 +1,30 @@
-use reqwest::Client;
-use serde::Deserialize;
+use super::asset_cache::{AssetCache, AssetKind, Fetched};
+
+use reqwest::header::{ETAG, IF_NONE_MATCH, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Deserializer};
+use serenity::async_trait;
 use serenity::{all::json, futures::future::join_all};
 use std::env;
 use std::error::Error;
 use std::fmt;
-use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::data_source::OsuDataSource;
+use super::rate_limiter::{backoff_delay, RateLimiter};
 
 #[derive(Debug, Deserialize)]
-struct ScoreResponse {
-    scores: Vec<Score>,
+pub struct ScoreResponse {
+    pub scores: Vec<Score>,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct BeatmapUserScore {
+    pub score: Score,
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct Score {
     pub classic_total_score: i64,
     pub legacy_total_score: i64,
@@ -21,56 +34,401 @@ pub struct Score {
     pub mods: Vec<Mod>,
     pub max_combo: i32,
     pub accuracy: f32,
+    // Missing from a user's scores on a beatmap, see `fetch_user_beatmap_scores`
+    #[serde(default)]
     pub user: User,
 }
 
-#[derive(Debug, Deserialize)]
+impl Score {
+    pub fn total_score(&self, scoring: ScoringMode) -> i64 {
+        match scoring {
+            ScoringMode::Legacy => self.legacy_total_score,
+            ScoringMode::Lazer => self.classic_total_score,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
 pub struct Statistics {
+    #[serde(default)]
+    pub perfect: Option<i32>,
+    #[serde(default)]
+    pub great: Option<i32>,
+    #[serde(default)]
+    pub good: Option<i32>,
     #[serde(default)]
     pub ok: Option<i32>,
     #[serde(default)]
     pub miss: Option<i32>,
     #[serde(default)]
     pub meh: Option<i32>,
+    #[serde(default)]
+    pub large_tick_hit: Option<i32>,
+    #[serde(default)]
+    pub small_tick_hit: Option<i32>,
+    #[serde(default)]
+    pub small_tick_miss: Option<i32>,
+    #[serde(default)]
+    pub slider_tail_hit: Option<i32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Mod {
     pub acronym: String,
+    // Only lazer scores have settings, stable mods always use their defaults
+    #[serde(default)]
+    pub settings: ModSettings,
 }
 
-#[derive(Debug, Deserialize)]
+// The settings this bot understands, osu! sends more for some lazer mods
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct ModSettings {
+    // DT, NC, HT and DC
+    #[serde(default)]
+    pub speed_change: Option<f64>,
+    // Difficulty adjust
+    #[serde(default)]
+    pub circle_size: Option<f64>,
+    #[serde(default)]
+    pub approach_rate: Option<f64>,
+    #[serde(default)]
+    pub overall_difficulty: Option<f64>,
+    #[serde(default)]
+    pub drain_rate: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct User {
     pub id: i64,
     pub username: String,
     pub avatar_url: String,
+    #[serde(default)]
+    pub country_code: String,
+}
+
+// A user with everything shown on their profile, for one game mode
+#[derive(Debug, Deserialize)]
+pub struct UserProfile {
+    #[serde(flatten)]
+    pub user: User,
+    // The user's default mode, which the profile is for unless another one was requested
+    pub playmode: GameMode,
+    #[serde(default)]
+    pub cover: Option<UserCover>,
+    pub statistics: UserStatistics,
+    #[serde(default)]
+    pub rank_history: Option<RankHistory>,
+    #[serde(skip)]
+    pub avatar_image: Vec<u8>,
+    #[serde(skip)]
+    pub cover_image: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserCover {
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserStatistics {
+    // Missing for inactive users
+    #[serde(default)]
+    pub global_rank: Option<u32>,
+    #[serde(default)]
+    pub country_rank: Option<u32>,
+    pub pp: f32,
+    // In percent
+    pub hit_accuracy: f32,
+    pub play_count: u32,
+    // In seconds
+    #[serde(default)]
+    pub play_time: u64,
+    pub grade_counts: GradeCounts,
+    pub level: UserLevel,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GradeCounts {
+    pub ssh: i32,
+    pub ss: i32,
+    pub sh: i32,
+    pub s: i32,
+    pub a: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserLevel {
+    pub current: u32,
+    // In percent towards the next level
+    pub progress: u32,
+}
+
+// Daily global ranks of the last 90 days, oldest first
+#[derive(Debug, Deserialize)]
+pub struct RankHistory {
+    pub data: Vec<u32>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Beatmap {
-    pub artist: String,
-    pub title: String,
+    pub id: i64,
+    pub beatmapset_id: i64,
+    pub mode: GameMode,
     pub version: String,
-    pub beatmapset_id: String,
-    pub beatmap_id: String,
-    pub max_combo: String,
+    pub difficulty_rating: f32,
+    #[serde(default)]
+    pub max_combo: i32,
+    pub bpm: f32,
+    // In seconds
+    pub total_length: u32,
+    pub cs: f32,
+    pub ar: f32,
+    #[serde(rename = "accuracy")]
+    pub od: f32,
+    #[serde(rename = "drain")]
+    pub hp: f32,
+    pub status: BeatmapStatus,
+    #[serde(default)]
+    pub convert: bool,
+    #[serde(default)]
+    pub owners: Vec<BeatmapOwner>,
+    // Not included for the difficulties of a beatmapset response
+    #[serde(default)]
+    pub beatmapset: Beatmapset,
     #[serde(skip)]
     pub cover: Vec<u8>,
 }
 
+impl Beatmap {
+    // Guest difficulties are credited to their owners instead of the set's creator
+    pub fn mapper(&self) -> String {
+        if self.owners.is_empty() {
+            return self.beatmapset.creator.clone();
+        }
+
+        self.owners
+            .iter()
+            .map(|o| o.username.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Beatmapset {
+    pub artist: String,
+    pub title: String,
+    pub creator: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BeatmapOwner {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BeatmapStatus {
+    Graveyard,
+    Wip,
+    Pending,
+    Ranked,
+    Approved,
+    Qualified,
+    Loved,
+}
+
+impl BeatmapStatus {
+    // Beatmaps in these states can't be updated anymore
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Ranked | Self::Approved | Self::Loved)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Graveyard => "Graveyard",
+            Self::Wip => "WIP",
+            Self::Pending => "Pending",
+            Self::Ranked => "Ranked",
+            Self::Approved => "Approved",
+            Self::Qualified => "Qualified",
+            Self::Loved => "Loved",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BeatmapsetResponse {
+    #[serde(flatten)]
+    pub beatmapset: Beatmapset,
+    pub beatmaps: Vec<Beatmap>,
+    #[serde(default)]
+    pub converts: Vec<Beatmap>,
+}
+
+impl BeatmapsetResponse {
+    // Difficulties playable in `mode`, including converts, with the set's metadata attached
+    pub fn into_difficulties(self, mode: Option<GameMode>) -> Vec<Beatmap> {
+        let beatmapset = self.beatmapset;
+
+        self.beatmaps
+            .into_iter()
+            .chain(self.converts)
+            .filter(|b| match mode {
+                Some(mode) => b.mode == mode,
+                None => !b.convert,
+            })
+            .map(|mut b| {
+                b.beatmapset = beatmapset.clone();
+                b
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DifficultyAttributesResponse {
+    attributes: DifficultyAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+struct DifficultyAttributes {
+    star_rating: f32,
+    #[serde(default)]
+    max_combo: i32,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct RecetScoreBeatmap {
     pub id: i64,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize)]
 pub struct RecentScore {
+    #[serde(flatten)]
+    pub score: Score,
     pub beatmap: RecetScoreBeatmap,
+    #[serde(rename = "ruleset_id", deserialize_with = "deserialize_ruleset_id")]
+    pub mode: GameMode,
+    pub passed: bool,
+    // Only set for passes on ranked beatmaps
+    #[serde(default)]
+    pub pp: Option<f32>,
+}
+
+// Score from a user's top plays, with the beatmap it was set on
+#[derive(Debug, Deserialize)]
+pub struct BestScore {
+    #[serde(flatten)]
+    pub score: Score,
+    pub beatmap: Beatmap,
+    pub beatmapset: Beatmapset,
+    #[serde(rename = "ruleset_id", deserialize_with = "deserialize_ruleset_id")]
+    pub mode: GameMode,
+    #[serde(default)]
+    pub pp: Option<f32>,
+}
+
+fn deserialize_ruleset_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<GameMode, D::Error> {
+    match u8::deserialize(deserializer)? {
+        0 => Ok(GameMode::Osu),
+        1 => Ok(GameMode::Taiko),
+        2 => Ok(GameMode::Fruits),
+        3 => Ok(GameMode::Mania),
+        id => Err(serde::de::Error::custom(format!("unknown ruleset {}", id))),
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
     access_token: String,
+    expires_in: u64,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScoringMode {
+    Legacy,
+    Lazer,
+}
+
+impl ScoringMode {
+    pub fn from_lazer_flag(lazer: bool) -> Self {
+        match lazer {
+            true => Self::Lazer,
+            false => Self::Legacy,
+        }
+    }
+
+    fn legacy_only_param(&self) -> i32 {
+        match self {
+            Self::Legacy => 1,
+            Self::Lazer => 0,
+        }
+    }
+}
+
+// Leaderboards served by the website, relative to the session account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreBoard {
+    Global,
+    Country,
+    Friends,
+}
+
+impl ScoreBoard {
+    pub fn as_param(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Country => "country",
+            Self::Friends => "friend",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    Osu,
+    Taiko,
+    Fruits,
+    Mania,
+}
+
+impl GameMode {
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "osu" | "std" | "standard" | "0" => Some(Self::Osu),
+            "taiko" | "1" => Some(Self::Taiko),
+            "fruits" | "catch" | "ctb" | "2" => Some(Self::Fruits),
+            "mania" | "3" => Some(Self::Mania),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Osu => "osu",
+            Self::Taiko => "taiko",
+            Self::Fruits => "fruits",
+            Self::Mania => "mania",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Osu => "osu!",
+            Self::Taiko => "osu!taiko",
+            Self::Fruits => "osu!catch",
+            Self::Mania => "osu!mania",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -80,6 +438,9 @@ pub enum OsuApiError {
     NotFound(String),
     MissingEnvVar(String),
     ImageError(String),
+    Unauthorized(String),
+    RateLimited(Option<Duration>),
+    Server(String),
 }
 
 impl fmt::Display for OsuApiError {
@@ -90,263 +451,598 @@ impl fmt::Display for OsuApiError {
             Self::NotFound(msg) => write!(f, "Not found: {}", msg),
             Self::MissingEnvVar(msg) => write!(f, "Missing environment variable: {}", msg),
             Self::ImageError(msg) => write!(f, "Image error: {}", msg),
+            Self::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            Self::RateLimited(Some(retry_after)) => {
+                write!(f, "Rate limited, retry after {}s", retry_after.as_secs())
+            }
+            Self::RateLimited(None) => write!(f, "Rate limited"),
+            Self::Server(msg) => write!(f, "Server error: {}", msg),
         }
     }
 }
 
 impl Error for OsuApiError {}
 
-pub static LEGACY_SCORE_ONLY: OnceLock<Mutex<bool>> = OnceLock::new();
+impl OsuApiError {
+    fn from_status(status: StatusCode, retry_after: Option<Duration>, resource: &str) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Self::Unauthorized(format!("{} ({})", resource, status))
+            }
+            StatusCode::NOT_FOUND => Self::NotFound(format!("{} not found", resource)),
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited(retry_after),
+            s if s.is_server_error() => Self::Server(format!("{} ({})", resource, status)),
+            _ => Self::RequestFailed(format!("{} ({})", resource, status)),
+        }
+    }
+
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::RateLimited(_) | Self::Server(_))
+    }
+}
+
+// Tokens are refreshed this long before osu! reports them as expired
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
 
-pub async fn handle_legacy_score_only(msg: &str) {
-    let msg_args = msg.split_whitespace().collect::<Vec<&str>>();
-    let has_lazer_flag = msg_args.contains(&"-l");
+// osu! allows 1200 requests per minute but asks to stay around 60 unless necessary
+const RATE_LIMIT_BURST: u32 = 60;
+const RATE_LIMIT_PER_MINUTE: u32 = 60;
 
-    let should_update = {
-        let legacy_lock = LEGACY_SCORE_ONLY.get().unwrap();
-        let legacy_state = legacy_lock.lock().unwrap();
-        has_lazer_flag == *legacy_state
-    };
+// Newer API versions return scores in the same format as the website
+const API_VERSION: &str = "20240529";
 
-    if !should_update {
-        return;
+// Enough to count the tries of the most recent play
+const RECENT_SCORES_LIMIT: u32 = 50;
+
+// The API doesn't return more than a user's top 100
+const BEST_SCORES_LIMIT: u32 = 100;
+
+// Images come from osu!'s CDN and a slow one shouldn't hold up the reply
+const ASSET_TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+// Longer Retry-After waits fail the request instead of holding up the command
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+pub struct OsuClient {
+    http: Client,
+    token: Mutex<Option<CachedToken>>,
+    rate_limiter: RateLimiter,
+    assets: AssetCache,
+}
+
+impl OsuClient {
+    pub fn new() -> Self {
+        Self {
+            http: Client::new(),
+            token: Mutex::new(None),
+            rate_limiter: RateLimiter::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_MINUTE),
+            assets: AssetCache::from_env(),
+        }
     }
 
-    if let Ok(_) = set_legacy_score_only(!has_lazer_flag).await {
-        let legacy_lock = LEGACY_SCORE_ONLY.get().unwrap();
-        let mut legacy_state = legacy_lock.lock().unwrap();
-        *legacy_state = !has_lazer_flag;
+    // Sends a rate limited request to osu!, retrying transient failures with backoff,
+    // and returns the response body. `resource` describes the request in error messages.
+    async fn send(&self, request: RequestBuilder, resource: &str) -> Result<String, OsuApiError> {
+        let mut attempt = 0;
+
+        loop {
+            let request = request.try_clone().ok_or_else(|| {
+                OsuApiError::RequestFailed("Request can't be retried".to_string())
+            })?;
+
+            self.rate_limiter.acquire().await;
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    return response
+                        .text()
+                        .await
+                        .map_err(|e| OsuApiError::RequestFailed(e.to_string()));
+                }
+                Ok(response) => {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u64>().ok())
+                        .map(Duration::from_secs);
+
+                    OsuApiError::from_status(response.status(), retry_after, resource)
+                }
+                Err(e) if e.is_timeout() || e.is_connect() => OsuApiError::Server(e.to_string()),
+                Err(e) => return Err(OsuApiError::RequestFailed(e.to_string())),
+            };
+
+            if !error.is_transient() || attempt >= MAX_RETRIES {
+                return Err(error);
+            }
+
+            let delay = match error {
+                OsuApiError::RateLimited(Some(retry_after)) if retry_after > MAX_RETRY_AFTER => {
+                    return Err(error);
+                }
+                OsuApiError::RateLimited(Some(retry_after)) => retry_after,
+                _ => backoff_delay(RETRY_BASE_DELAY, attempt),
+            };
+            println!(
+                "osu! API request for {} failed ({}), retrying in {:?}",
+                resource, error, delay
+            );
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
-}
 
-pub fn get_legacy_score_only_status() -> bool {
-    let legacy_lock = LEGACY_SCORE_ONLY.get().unwrap();
-    let legacy_state = legacy_lock.lock().unwrap();
-    *legacy_state
-}
+    async fn fetch_image(&self, kind: AssetKind, url: &str) -> Result<Vec<u8>, OsuApiError> {
+        self.assets
+            .get_or_fetch(kind, url, |etag| self.download_image(url, etag))
+            .await
+    }
 
-pub async fn set_legacy_score_only(value: bool) -> Result<(), OsuApiError> {
-    let osu_session = env::var("OSU_SESSION")
-        .map_err(|_| OsuApiError::MissingEnvVar("OSU_SESSION".to_string()))?;
-    let xsrf_token =
-        env::var("XSRF_TOKEN").map_err(|_| OsuApiError::MissingEnvVar("XSRF_TOKEN".to_string()))?;
+    // Asks only for changes when the cached copy has an ETag
+    async fn download_image(
+        &self,
+        url: &str,
+        etag: Option<String>,
+    ) -> Result<Fetched, OsuApiError> {
+        let mut request = self.http.get(url).timeout(ASSET_TIMEOUT);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
 
-    let url = format!(
-        "https://osu.ppy.sh/home/account/options?user_profile_customization[legacy_score_only]={}",
-        value as i32
-    );
+        let response = request
+            .send()
+            .await
+            .map_err(|e| OsuApiError::ImageError(e.to_string()))?;
 
-    let client = Client::new();
-    let response = client
-        .put(url)
-        .header("Cookie", format!("osu_session={osu_session}"))
-        .header("X-CSRF-Token", xsrf_token)
-        .header("Content-Length", "0")
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .send()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+
+        if !response.status().is_success() {
+            return Err(OsuApiError::ImageError(format!(
+                "{} returned {}",
+                url,
+                response.status()
+            )));
+        }
 
-    if response.status().is_success() {
-        Ok(())
-    } else {
-        Err(OsuApiError::RequestFailed(format!(
-            "Failed to set legacy score only: {}",
-            response.status()
-        )))
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|e| OsuApiError::ImageError(e.to_string()))?;
+
+        Ok(Fetched::Modified {
+            bytes: bytes.to_vec(),
+            etag,
+        })
     }
-}
 
-async fn get_client_credentials_token() -> Result<String, OsuApiError> {
-    let client_id =
-        env::var("CLIENT_ID").map_err(|_| OsuApiError::MissingEnvVar("CLIENT_ID".to_string()))?;
-    let client_secret = env::var("CLIENT_SECRET")
-        .map_err(|_| OsuApiError::MissingEnvVar("CLIENT_SECRET".to_string()))?;
+    // Images are drawn with a fallback when missing, so failing to load one isn't an error
+    async fn fetch_image_or_default(&self, kind: AssetKind, url: &str) -> Vec<u8> {
+        match self.fetch_image(kind, url).await {
+            Ok(image) => image,
+            Err(e) => {
+                println!("Failed to fetch image {}: {}", url, e);
+                Vec::new()
+            }
+        }
+    }
+
+    async fn download_osu_file(&self, beatmap_id: i64) -> Result<Vec<u8>, OsuApiError> {
+        let url = format!("https://osu.ppy.sh/osu/{beatmap_id}");
+
+        let osu_file = self
+            .send(self.http.get(&url), &format!("Beatmap file {}", beatmap_id))
+            .await?;
+
+        // osu! answers unknown beatmaps with an empty file instead of a 404
+        if osu_file.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "Beatmap file {} not found",
+                beatmap_id
+            )));
+        }
+
+        Ok(osu_file.into_bytes())
+    }
 
-    let client = Client::new();
-    let response = client
-        .post("https://osu.ppy.sh/oauth/token")
-        .form(&[
+    async fn get_client_credentials_token(&self) -> Result<String, OsuApiError> {
+        // Holding the lock while refreshing makes concurrent callers wait for one token request
+        let mut cached_token = self.token.lock().await;
+
+        if let Some(token) = cached_token.as_ref() {
+            if token.expires_at > Instant::now() + TOKEN_EXPIRY_MARGIN {
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        let client_id = env::var("CLIENT_ID")
+            .map_err(|_| OsuApiError::MissingEnvVar("CLIENT_ID".to_string()))?;
+        let client_secret = env::var("CLIENT_SECRET")
+            .map_err(|_| OsuApiError::MissingEnvVar("CLIENT_SECRET".to_string()))?;
+
+        let request = self.http.post("https://osu.ppy.sh/oauth/token").form(&[
             ("client_id", client_id),
             ("client_secret", client_secret),
             ("grant_type", "client_credentials".to_string()),
             ("scope", "public".to_string()),
-        ])
-        .send()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?
-        .text()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?;
-
-    let token_response = json::from_str::<TokenResponse>(&response)
-        .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
-
-    Ok(token_response.access_token)
-}
-
-pub async fn fetch_country_scores(beatmap_id: &str) -> Result<Vec<Score>, OsuApiError> {
-    let osu_session = env::var("OSU_SESSION")
-        .map_err(|_| OsuApiError::MissingEnvVar("OSU_SESSION".to_string()))?;
-    let xsrf_token =
-        env::var("XSRF_TOKEN").map_err(|_| OsuApiError::MissingEnvVar("XSRF_TOKEN".to_string()))?;
-
-    let url =
-        format!("https://osu.ppy.sh/beatmaps/{beatmap_id}/scores?mode=osu&type=country&limit=99");
-
-    let client = Client::new();
-    let response = client
-        .get(&url)
-        .header("Cookie", format!("osu_session={osu_session}"))
-        .header("X-CSRF-Token", xsrf_token)
-        .send()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?
-        .text()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?;
-
-    let scores = json::from_str::<ScoreResponse>(&response)
-        .map_err(|e| OsuApiError::ParseError(e.to_string()))?
-        .scores;
-
-    if scores.is_empty() {
-        return Err(OsuApiError::NotFound(
-            "No country scores found for this beatmap".to_string(),
-        ));
-    }
-
-    Ok(scores)
-}
-
-pub async fn fetch_beatmap_info(beatmap_id: &str) -> Result<Beatmap, OsuApiError> {
-    let osu_api_key = env::var("OSU_API_KEY")
-        .map_err(|_| OsuApiError::MissingEnvVar("OSU_API_KEY".to_string()))?;
-
-    let url = format!("https://osu.ppy.sh/api/get_beatmaps?k={osu_api_key}&b={beatmap_id}&m=0");
-
-    let client = Client::new();
-    let response = client
-        .get(&url)
-        .send()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?
-        .text()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?;
-
-    let mut beatmaps: Vec<Beatmap> =
-        json::from_str(&response).map_err(|e| OsuApiError::ParseError(e.to_string()))?;
-
-    let beatmap = beatmaps
-        .first_mut()
-        .ok_or_else(|| OsuApiError::NotFound(format!("Beatmap {} not found", beatmap_id)))?;
-
-    let cover_url = format!(
-        "https://assets.ppy.sh/beatmaps/{}/covers/cover.jpg",
-        beatmap.beatmapset_id
-    );
-
-    let cover_bytes = client
-        .get(&cover_url)
-        .send()
-        .await
-        .map_err(|e| OsuApiError::ImageError(e.to_string()))?
-        .bytes()
-        .await
-        .map_err(|e| OsuApiError::ImageError(e.to_string()))?;
-
-    beatmap.cover = cover_bytes.to_vec();
-    Ok(beatmap.clone())
-}
-
-pub async fn get_avatars_bytes_array(scores: &Vec<Score>) -> Result<Vec<Vec<u8>>, OsuApiError> {
-    let futures: Vec<_> = scores
-        .iter()
-        .map(|s| async {
-            let response = reqwest::get(&s.user.avatar_url)
-                .await
-                .map_err(|e| OsuApiError::ImageError(e.to_string()))?;
-
-            response
-                .bytes()
-                .await
-                .map_err(|e| OsuApiError::ImageError(e.to_string()))
-                .map(|b| b.to_vec())
-        })
-        .collect();
+        ]);
+        let response = self.send(request, "OAuth token").await?;
+
+        let token_response = json::from_str::<TokenResponse>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
 
-    join_all(futures)
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>, _>>()
+        *cached_token = Some(CachedToken {
+            access_token: token_response.access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(token_response.expires_in),
+        });
+
+        Ok(token_response.access_token)
+    }
 }
 
-pub async fn get_user_id(user: &str) -> Result<String, OsuApiError> {
-    if let Ok(id) = user.parse::<i64>() {
-        return Ok(id.to_string());
+#[async_trait]
+impl OsuDataSource for OsuClient {
+    async fn fetch_scores(
+        &self,
+        beatmap_id: &str,
+        mode: GameMode,
+        scoring: ScoringMode,
+        board: ScoreBoard,
+    ) -> Result<Vec<Score>, OsuApiError> {
+        let osu_session = env::var("OSU_SESSION")
+            .map_err(|_| OsuApiError::MissingEnvVar("OSU_SESSION".to_string()))?;
+        let xsrf_token = env::var("XSRF_TOKEN")
+            .map_err(|_| OsuApiError::MissingEnvVar("XSRF_TOKEN".to_string()))?;
+
+        let url = format!(
+            "https://osu.ppy.sh/beatmaps/{beatmap_id}/scores?mode={}&type={}&limit=99&legacy_only={}",
+            mode.as_str(),
+            board.as_param(),
+            scoring.legacy_only_param()
+        );
+
+        let request = self
+            .http
+            .get(&url)
+            .header("Cookie", format!("osu_session={osu_session}"))
+            .header("X-CSRF-Token", xsrf_token);
+        let response = self
+            .send(request, &format!("Scores of beatmap {}", beatmap_id))
+            .await?;
+
+        let scores = json::from_str::<ScoreResponse>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?
+            .scores;
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(
+                "No scores found on this leaderboard".to_string(),
+            ));
+        }
+
+        Ok(scores)
+    }
+
+    async fn fetch_user_beatmap_score(
+        &self,
+        beatmap_id: &str,
+        user_id: i64,
+        mode: GameMode,
+        scoring: ScoringMode,
+    ) -> Result<Score, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
+
+        let url = format!(
+            "https://osu.ppy.sh/api/v2/beatmaps/{beatmap_id}/scores/users/{user_id}?mode={}&legacy_only={}",
+            mode.as_str(),
+            scoring.legacy_only_param()
+        );
+
+        let request = self
+            .http
+            .get(&url)
+            .bearer_auth(token)
+            .header("x-api-version", API_VERSION);
+        let response = self
+            .send(
+                request,
+                &format!("Score of user {} on beatmap {}", user_id, beatmap_id),
+            )
+            .await?;
+
+        let score = json::from_str::<BeatmapUserScore>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?
+            .score;
+
+        Ok(score)
     }
 
-    let token = match get_client_credentials_token().await {
-        Ok(t) => t,
-        Err(e) => {
-            return Err(e);
+    async fn fetch_user_beatmap_scores(
+        &self,
+        beatmap_id: &str,
+        user: &User,
+        mode: GameMode,
+        scoring: ScoringMode,
+    ) -> Result<Vec<Score>, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
+
+        let url = format!(
+            "https://osu.ppy.sh/api/v2/beatmaps/{beatmap_id}/scores/users/{}/all?mode={}&legacy_only={}",
+            user.id,
+            mode.as_str(),
+            scoring.legacy_only_param()
+        );
+
+        let request = self
+            .http
+            .get(&url)
+            .bearer_auth(token)
+            .header("x-api-version", API_VERSION);
+        let response = self
+            .send(
+                request,
+                &format!("Scores of user {} on beatmap {}", user.id, beatmap_id),
+            )
+            .await?;
+
+        let mut scores = json::from_str::<ScoreResponse>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?
+            .scores;
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "{} has no scores on this beatmap",
+                user.username
+            )));
         }
-    };
 
-    let url = format!("https://osu.ppy.sh/api/v2/users/{user}");
+        // This endpoint doesn't include the user with each score
+        for score in scores.iter_mut() {
+            score.user = user.clone();
+        }
 
-    let client = Client::new();
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", token))
-        .send()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?
-        .text()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?;
+        Ok(scores)
+    }
 
-    let user =
-        json::from_str::<User>(&response).map_err(|e| OsuApiError::ParseError(e.to_string()))?;
+    async fn fetch_beatmap_info(
+        &self,
+        beatmap_id: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Beatmap, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
 
-    Ok(user.id.to_string())
-}
+        let url = format!("https://osu.ppy.sh/api/v2/beatmaps/{beatmap_id}");
+
+        let request = self.http.get(&url).bearer_auth(&token);
+        let response = self
+            .send(request, &format!("Beatmap {}", beatmap_id))
+            .await?;
+
+        let mut beatmap = json::from_str::<Beatmap>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
 
-pub async fn get_user_recent(user: &str) -> Result<RecentScore, OsuApiError> {
-    let token = match get_client_credentials_token().await {
-        Ok(t) => t,
-        Err(e) => {
-            return Err(e);
+        // Converts have their own star rating and max combo
+        if let Some(mode) = mode.filter(|m| *m != beatmap.mode) {
+            if beatmap.mode != GameMode::Osu {
+                return Err(OsuApiError::NotFound(format!(
+                    "Beatmap {} has no {} version",
+                    beatmap_id,
+                    mode.as_str()
+                )));
+            }
+
+            let request = self
+                .http
+                .post(format!("{url}/attributes"))
+                .bearer_auth(&token)
+                .form(&[("ruleset", mode.as_str())]);
+            let response = self
+                .send(request, &format!("Attributes of beatmap {}", beatmap_id))
+                .await?;
+
+            let attributes = json::from_str::<DifficultyAttributesResponse>(&response)
+                .map_err(|e| OsuApiError::ParseError(e.to_string()))?
+                .attributes;
+
+            beatmap.mode = mode;
+            beatmap.convert = true;
+            beatmap.difficulty_rating = attributes.star_rating;
+            beatmap.max_combo = attributes.max_combo;
         }
-    };
 
-    let user_id = match get_user_id(user).await {
-        Ok(id) => id,
-        Err(e) => {
-            return Err(e);
+        let cover_url = format!(
+            "https://assets.ppy.sh/beatmaps/{}/covers/cover.jpg",
+            beatmap.beatmapset_id
+        );
+
+        beatmap.cover = self
+            .fetch_image_or_default(AssetKind::Cover, &cover_url)
+            .await;
+        Ok(beatmap)
+    }
+
+    async fn fetch_beatmapset(
+        &self,
+        beatmapset_id: i64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<Beatmap>, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
+
+        let url = format!("https://osu.ppy.sh/api/v2/beatmapsets/{beatmapset_id}");
+
+        let request = self.http.get(&url).bearer_auth(token);
+        let response = self
+            .send(request, &format!("Beatmapset {}", beatmapset_id))
+            .await?;
+
+        let beatmaps = json::from_str::<BeatmapsetResponse>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?
+            .into_difficulties(mode);
+
+        if beatmaps.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "Beatmapset {} not found",
+                beatmapset_id
+            )));
         }
-    };
-
-    let url =
-        format!("https://osu.ppy.sh/api/v2/users/{user_id}/scores/recent?limit=1&include_fails=1");
-
-    let client = Client::new();
-    let response = client
-        .get(&url)
-        .header("Authorization", format!("Bearer {}", token))
-        .send()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?
-        .text()
-        .await
-        .map_err(|e| OsuApiError::RequestFailed(e.to_string()))?;
-
-    let scores = json::from_str::<Vec<RecentScore>>(&response)
-        .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
-
-    scores
-        .first()
-        .cloned()
-        .ok_or_else(|| OsuApiError::NotFound(format!("No recent scores for user {}", user)))
+
+        Ok(beatmaps)
+    }
+
+    // Unranked beatmaps can still be updated, so only ranked and loved ones are cached for good
+    async fn fetch_osu_file(&self, beatmap: &Beatmap) -> Result<Vec<u8>, OsuApiError> {
+        let kind = match beatmap.status.is_final() {
+            true => AssetKind::FinalBeatmapFile,
+            false => AssetKind::BeatmapFile,
+        };
+
+        self.assets
+            .get_or_fetch(kind, &format!("{}.osu", beatmap.id), |_| async {
+                let bytes = self.download_osu_file(beatmap.id).await?;
+                Ok(Fetched::Modified { bytes, etag: None })
+            })
+            .await
+    }
+
+    async fn get_avatars_bytes_array(&self, scores: &[&Score]) -> Vec<Vec<u8>> {
+        let futures: Vec<_> = scores
+            .iter()
+            .map(|s| self.fetch_image_or_default(AssetKind::Avatar, &s.user.avatar_url))
+            .collect();
+
+        join_all(futures).await
+    }
+
+    async fn fetch_user(&self, user: &str) -> Result<User, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
+
+        let url = format!("https://osu.ppy.sh/api/v2/users/{user}");
+
+        let request = self.http.get(&url).bearer_auth(token);
+        let response = self.send(request, &format!("User {}", user)).await?;
+
+        json::from_str::<User>(&response).map_err(|e| OsuApiError::ParseError(e.to_string()))
+    }
+
+    async fn get_user_id(&self, user: &str) -> Result<String, OsuApiError> {
+        if let Ok(id) = user.parse::<i64>() {
+            return Ok(id.to_string());
+        }
+
+        Ok(self.fetch_user(user).await?.id.to_string())
+    }
+
+    async fn fetch_user_profile(
+        &self,
+        user: &str,
+        mode: Option<GameMode>,
+    ) -> Result<UserProfile, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
+
+        let mut url = format!("https://osu.ppy.sh/api/v2/users/{user}");
+        if let Some(mode) = mode {
+            url.push_str(&format!("/{}", mode.as_str()));
+        }
+
+        let request = self.http.get(&url).bearer_auth(token);
+        let response = self.send(request, &format!("User {}", user)).await?;
+
+        let mut profile = json::from_str::<UserProfile>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
+
+        profile.avatar_image = self
+            .fetch_image_or_default(AssetKind::Avatar, &profile.user.avatar_url)
+            .await;
+        if let Some(cover) = &profile.cover {
+            profile.cover_image = self
+                .fetch_image_or_default(AssetKind::Cover, &cover.url)
+                .await;
+        }
+
+        Ok(profile)
+    }
+
+    async fn fetch_user_best(
+        &self,
+        user_id: i64,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<BestScore>, OsuApiError> {
+        let token = self.get_client_credentials_token().await?;
+
+        let mut url = format!(
+            "https://osu.ppy.sh/api/v2/users/{user_id}/scores/best?limit={BEST_SCORES_LIMIT}"
+        );
+        if let Some(mode) = mode {
+            url.push_str(&format!("&mode={}", mode.as_str()));
+        }
+
+        let request = self
+            .http
+            .get(&url)
+            .bearer_auth(token)
+            .header("x-api-version", API_VERSION);
+        let response = self
+            .send(request, &format!("Top plays of user {}", user_id))
+            .await?;
+
+        let scores = json::from_str::<Vec<BestScore>>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "No top plays for user {}",
+                user_id
+            )));
+        }
+
+        Ok(scores)
+    }
+
+    async fn get_user_recent(
+        &self,
+        user: &str,
+        mode: Option<GameMode>,
+    ) -> Result<Vec<RecentScore>, OsuApiError> {
+        let user_id = self.get_user_id(user).await?;
+        let token = self.get_client_credentials_token().await?;
+
+        let mut url = format!(
+            "https://osu.ppy.sh/api/v2/users/{user_id}/scores/recent?limit={RECENT_SCORES_LIMIT}&include_fails=1"
+        );
+        if let Some(mode) = mode {
+            url.push_str(&format!("&mode={}", mode.as_str()));
+        }
+
+        let request = self
+            .http
+            .get(&url)
+            .bearer_auth(token)
+            .header("x-api-version", API_VERSION);
+        let response = self
+            .send(request, &format!("Recent scores of user {}", user))
+            .await?;
+
+        let scores = json::from_str::<Vec<RecentScore>>(&response)
+            .map_err(|e| OsuApiError::ParseError(e.to_string()))?;
+
+        if scores.is_empty() {
+            return Err(OsuApiError::NotFound(format!(
+                "No recent scores for user {}",
+                user
+            )));
+        }
+
+        Ok(scores)
+    }
 }
@@ -0,0 +1,287 @@
+use super::mods::Mods;
+use super::osu_api::{Beatmap, GameMode, Score};
+
+use rosu_pp::model::mode::GameMode as RosuGameMode;
+use rosu_pp::model::mods::GameMods;
+use rosu_pp::{Difficulty, Performance};
+
+// Applies the mod bits, custom rate and difficulty adjust values to any of rosu-pp's
+// Difficulty, Performance and beatmap attributes builders, which share these setters
+macro_rules! with_mods {
+    ($builder:expr, $mods:expr) => {{
+        let mods: &Mods = $mods;
+        let mut builder = $builder.mods(mods.bits());
+        if let Some(rate) = mods.custom_clock_rate() {
+            builder = builder.clock_rate(rate);
+        }
+
+        // Not fixed, so a custom rate still changes AR and OD like in game
+        let settings = mods.difficulty_adjust();
+        if let Some(cs) = settings.circle_size {
+            builder = builder.cs(cs as f32, false);
+        }
+        if let Some(ar) = settings.approach_rate {
+            builder = builder.ar(ar as f32, false);
+        }
+        if let Some(od) = settings.overall_difficulty {
+            builder = builder.od(od as f32, false);
+        }
+        if let Some(hp) = settings.drain_rate {
+            builder = builder.hp(hp as f32, false);
+        }
+        builder
+    }};
+}
+
+// Difficulty settings and star rating with mods applied
+pub struct AdjustedStats {
+    pub stars: f64,
+    pub cs: f64,
+    pub ar: f64,
+    pub od: f64,
+    pub hp: f64,
+    // Speed of DT/NC (1.5) and HT (0.75) or a custom rate, for the length and BPM
+    pub clock_rate: f64,
+}
+
+impl AdjustedStats {
+    // The values osu! reports without mods, for when the beatmap file can't be calculated
+    pub fn nomod(beatmap: &Beatmap) -> Self {
+        Self {
+            stars: beatmap.difficulty_rating as f64,
+            cs: beatmap.cs as f64,
+            ar: beatmap.ar as f64,
+            od: beatmap.od as f64,
+            hp: beatmap.hp as f64,
+            clock_rate: 1.0,
+        }
+    }
+}
+
+pub struct PerformanceCalculator {
+    map: rosu_pp::Beatmap,
+    mode: GameMode,
+}
+
+impl PerformanceCalculator {
+    // None if the file can't be parsed, has no version for `mode` or is too suspicious
+    // (e.g. absurd object counts) to calculate in reasonable time
+    pub fn new(osu_file: &[u8], mode: GameMode) -> Option<Self> {
+        let mut map = rosu_pp::Beatmap::from_bytes(osu_file).ok()?;
+        map.convert_mut(rosu_mode(mode), &GameMods::from(0)).ok()?;
+        map.check_suspicion().ok()?;
+
+        Some(Self { map, mode })
+    }
+
+    pub fn stars(&self, mods: &Mods) -> f64 {
+        with_mods!(Difficulty::new(), mods)
+            .calculate(&self.map)
+            .stars()
+    }
+
+    pub fn adjusted_stats(&self, mods: &Mods) -> AdjustedStats {
+        let attributes = with_mods!(self.map.attributes(), mods).build();
+
+        AdjustedStats {
+            stars: self.stars(mods),
+            cs: attributes.cs,
+            ar: attributes.ar,
+            od: attributes.od,
+            hp: attributes.hp,
+            clock_rate: attributes.clock_rate,
+        }
+    }
+
+    pub fn score_pp(&self, score: &Score) -> f64 {
+        self.performance(score, false).calculate().pp()
+    }
+
+    // Failed plays only count the objects they got through
+    pub fn failed_score_pp(&self, score: &Score) -> f64 {
+        self.performance(score, false)
+            .passed_objects(self.passed_objects(score))
+            .calculate()
+            .pp()
+    }
+
+    // pp of the same play as a full combo, with misses turned into the best judgement and
+    // the objects a failed play didn't reach filled in by rosu-pp
+    pub fn fc_pp(&self, score: &Score) -> f64 {
+        self.performance(score, true).calculate().pp()
+    }
+
+    // How far into the beatmap a failed play got, from 0 to 1
+    pub fn completion(&self, score: &Score) -> f64 {
+        let objects = self.map.hit_objects.len().max(1) as f64;
+        (self.passed_objects(score) as f64 / objects).min(1.0)
+    }
+
+    // Hit objects up to the last one the play was judged on. Without CL, lazer judges the
+    // head and the tail of each mania hold note, so those take two judgements.
+    fn passed_objects(&self, score: &Score) -> u32 {
+        let judged = judgements(score, self.mode);
+        if self.mode != GameMode::Mania {
+            return judged;
+        }
+
+        let hold_judgements = match Mods::from_score(&score.mods).is_classic() {
+            true => 1,
+            false => 2,
+        };
+        let mut judgements = 0;
+        self.map
+            .hit_objects
+            .iter()
+            .take_while(|object| {
+                judgements += match object.is_hold_note() {
+                    true => hold_judgements,
+                    false => 1,
+                };
+                judgements <= judged
+            })
+            .count() as u32
+    }
+
+    fn performance(&self, score: &Score, full_combo: bool) -> Performance<'_> {
+        let mods = Mods::from_score(&score.mods);
+        let stats = &score.statistics;
+        let count = |value: Option<i32>| value.unwrap_or(0).max(0) as u32;
+        let (misses, fc_misses) = match full_combo {
+            true => (0, count(stats.miss)),
+            false => (count(stats.miss), 0),
+        };
+
+        // Scores set on stable carry CL and are calculated without lazer's slider changes
+        let performance = with_mods!(Performance::new(&self.map), &mods)
+            .lazer(!mods.is_classic())
+            .misses(misses);
+
+        // Without a combo rosu-pp assumes the beatmap's max combo
+        let performance = match full_combo {
+            true => performance,
+            false => performance.combo(score.max_combo.max(0) as u32),
+        };
+
+        match self.mode {
+            // Missed slider ticks and ends break combo, so a full combo hits all of them and
+            // rosu-pp fills in the maximums when they're left out
+            GameMode::Osu if full_combo => performance
+                .n300(count(stats.great) + fc_misses)
+                .n100(count(stats.ok))
+                .n50(count(stats.meh))
+                .small_tick_hits(count(stats.small_tick_hit)),
+            GameMode::Osu => performance
+                .n300(count(stats.great) + fc_misses)
+                .n100(count(stats.ok))
+                .n50(count(stats.meh))
+                .large_tick_hits(count(stats.large_tick_hit))
+                .small_tick_hits(count(stats.small_tick_hit))
+                .slider_end_hits(count(stats.slider_tail_hit)),
+            GameMode::Taiko => performance
+                .n300(count(stats.great) + fc_misses)
+                .n100(count(stats.ok)),
+            GameMode::Fruits => performance
+                .n300(count(stats.great) + fc_misses)
+                .n100(count(stats.large_tick_hit))
+                .n50(count(stats.small_tick_hit))
+                .n_katu(count(stats.small_tick_miss)),
+            GameMode::Mania => performance
+                .n_geki(count(stats.perfect) + fc_misses)
+                .n300(count(stats.great))
+                .n_katu(count(stats.good))
+                .n100(count(stats.ok))
+                .n50(count(stats.meh)),
+        }
+    }
+}
+
+// Judgements that end a hit object, so roughly the number of objects a play got through.
+// Droplets of osu!catch are hit objects of their own in scores but not in beatmap files.
+fn judgements(score: &Score, mode: GameMode) -> u32 {
+    let stats = &score.statistics;
+    let judgements = match mode {
+        GameMode::Osu => vec![stats.great, stats.ok, stats.meh, stats.miss],
+        GameMode::Taiko => vec![stats.great, stats.ok, stats.miss],
+        GameMode::Fruits => vec![stats.great, stats.miss],
+        GameMode::Mania => vec![
+            stats.perfect,
+            stats.great,
+            stats.good,
+            stats.ok,
+            stats.meh,
+            stats.miss,
+        ],
+    };
+
+    judgements
+        .into_iter()
+        .map(|count| count.unwrap_or(0).max(0) as u32)
+        .sum()
+}
+
+fn rosu_mode(mode: GameMode) -> RosuGameMode {
+    match mode {
+        GameMode::Osu => RosuGameMode::Osu,
+        GameMode::Taiko => RosuGameMode::Taiko,
+        GameMode::Fruits => RosuGameMode::Catch,
+        GameMode::Mania => RosuGameMode::Mania,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::all::json;
+
+    // A note, a hold note, a note and a hold note in 4K
+    const MANIA_MAP: &str = "osu file format v14
+
+[General]
+Mode: 3
+
+[Difficulty]
+HPDrainRate:8
+CircleSize:4
+OverallDifficulty:8
+ApproachRate:5
+SliderMultiplier:1.4
+SliderTickRate:1
+
+[TimingPoints]
+0,500,4,1,0,100,1,0
+
+[HitObjects]
+64,192,1000,1,0,0:0:0:0:
+192,192,1500,128,0,2000:0:0:0:0:
+320,192,2500,1,0,0:0:0:0:
+448,192,3000,128,0,3500:0:0:0:0:
+";
+
+    fn mania_score(great: i32, mods: &[&str]) -> Score {
+        let mods: Vec<String> = mods
+            .iter()
+            .map(|m| format!(r#"{{"acronym":"{}"}}"#, m))
+            .collect();
+        json::from_str(format!(
+            r#"{{"classic_total_score":0,"legacy_total_score":0,"ended_at":"",
+            "rank":"F","statistics":{{"great":{}}},"mods":[{}],"max_combo":{},
+            "accuracy":1.0}}"#,
+            great,
+            mods.join(","),
+            great
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn mania_hold_notes_take_two_judgements_in_lazer() {
+        let calculator = PerformanceCalculator::new(MANIA_MAP.as_bytes(), GameMode::Mania).unwrap();
+
+        // The note and the head and tail of the first hold note
+        assert_eq!(calculator.completion(&mania_score(3, &[])), 0.5);
+        // Stable judges hold notes once, so the third object was reached
+        assert_eq!(calculator.completion(&mania_score(3, &["CL"])), 0.75);
+        assert_eq!(calculator.completion(&mania_score(6, &[])), 1.0);
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+// Token bucket: up to `capacity` requests in a burst, refilled at a steady rate
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, requests_per_minute: u32) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec: requests_per_minute as f64 / 60.0,
+            state: Mutex::new(BucketState {
+                tokens: capacity as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    // Waits until a request may be sent and takes a token for it
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - state.tokens) / self.refill_per_sec)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// Exponential backoff with jitter: a random delay between half and all of base * 2^attempt
+pub fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    let max_delay = base * 2u32.pow(attempt.min(8));
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let jitter = nanos as f64 / 1_000_000_000.0;
+
+    max_delay.mul_f64(0.5 + jitter * 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The clock is paused, so time only moves while every task sleeps or when advanced
+    #[tokio::test(start_paused = true)]
+    async fn allows_a_burst_then_waits_for_refills() {
+        let limiter = RateLimiter::new(3, 600);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // One token every 100ms at 600 requests per minute
+        limiter.acquire().await;
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(99) && waited <= Duration::from_millis(101));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refills_up_to_the_burst_size() {
+        let limiter = RateLimiter::new(3, 600);
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        limiter.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(99));
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_within_jitter() {
+        let base = Duration::from_millis(500);
+        for attempt in 0..4 {
+            let max_delay = base * 2u32.pow(attempt);
+            let delay = backoff_delay(base, attempt);
+            assert!(delay >= max_delay / 2 && delay <= max_delay);
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let base = Duration::from_millis(500);
+        assert!(backoff_delay(base, 30) <= base * 256);
+    }
+}
@@ -0,0 +1,564 @@
+use super::beatmap_ref::{find_beatmap_ref, BeatmapRef};
+use super::channel_context;
+use super::commands::{self, CommandReply, LeaderboardOptions, TopPlaysOptions};
+use super::data_source::data_source;
+
+use serenity::all::{
+    ActionRowComponent, CommandInteraction, CommandOptionType, ComponentInteraction,
+    CreateActionRow, CreateAutocompleteResponse, CreateCommand, CreateCommandOption,
+    CreateInputText, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateModal,
+    EditInteractionResponse, InputTextStyle, ModalInteraction, ResolvedOption, ResolvedValue,
+};
+use serenity::prelude::*;
+
+pub fn create_commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("cs")
+            .description("Show the country leaderboard of a beatmap")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "beatmap",
+                "Beatmap ID or link (defaults to the last beatmap in this channel)",
+            ))
+            .add_option(mods_option())
+            .add_option(mode_option())
+            .add_option(lazer_option())
+            .add_option(type_option())
+            .add_option(page_option())
+            .add_option(player_option())
+            .add_option(refresh_option()),
+        CreateCommand::new("ss")
+            .description("Show the leaderboard of this server's members on a beatmap")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "beatmap",
+                "Beatmap ID or link (defaults to the last beatmap in this channel)",
+            ))
+            .add_option(mods_option())
+            .add_option(mode_option())
+            .add_option(lazer_option())
+            .add_option(page_option())
+            .add_option(player_option())
+            .add_option(refresh_option()),
+        CreateCommand::new("rs")
+            .description("Show your most recent play")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "user",
+                "osu! username or ID (defaults to your connected account)",
+            ))
+            .add_option(mode_option())
+            .add_option(lazer_option()),
+        CreateCommand::new("rsc")
+            .description("Show the country leaderboard of your most recent play")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "user",
+                "osu! username or ID (defaults to your connected account)",
+            ))
+            .add_option(mods_option())
+            .add_option(mode_option())
+            .add_option(lazer_option())
+            .add_option(type_option())
+            .add_option(page_option())
+            .add_option(player_option())
+            .add_option(refresh_option()),
+        CreateCommand::new("compare")
+            .description("Show all of a player's scores on a beatmap")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "beatmap",
+                "Beatmap ID or link (defaults to the last beatmap in this channel)",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "user",
+                "osu! username or ID (defaults to your connected account)",
+            ))
+            .add_option(mods_option())
+            .add_option(mode_option())
+            .add_option(lazer_option())
+            .add_option(page_option())
+            .add_option(refresh_option()),
+        CreateCommand::new("top")
+            .description("Show a player's top plays")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "user",
+                "osu! username or ID (defaults to your connected account)",
+            ))
+            .add_option(mods_option())
+            .add_option(mode_option())
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "rank",
+                    "Only show plays with this rank",
+                )
+                .add_string_choice("SS", "SS")
+                .add_string_choice("S", "S")
+                .add_string_choice("A", "A")
+                .add_string_choice("B", "B")
+                .add_string_choice("C", "C")
+                .add_string_choice("D", "D"),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "from",
+                "Only show plays set on or after this date (YYYY-MM-DD)",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "to",
+                "Only show plays set on or before this date (YYYY-MM-DD)",
+            ))
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "sort", "Sort plays by")
+                    .add_string_choice("pp", "pp")
+                    .add_string_choice("Accuracy", "acc")
+                    .add_string_choice("Combo", "combo")
+                    .add_string_choice("Date", "date"),
+            )
+            .add_option(page_option()),
+        CreateCommand::new("profile")
+            .description("Show a player's profile card")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "user",
+                "osu! username or ID (defaults to your connected account)",
+            ))
+            .add_option(mode_option()),
+        CreateCommand::new("map")
+            .description("Show a beatmap's info card")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "beatmap",
+                "Beatmap ID or link (defaults to the last beatmap in this channel)",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "mods",
+                    "Show stats with these mods, e.g. HDDT or DT1.3",
+                )
+                .set_autocomplete(true),
+            )
+            .add_option(mode_option()),
+        CreateCommand::new("connect")
+            .description("Connect your Discord account with your osu! account")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::Integer, "osu_id", "Your osu! user ID")
+                    .required(true)
+                    .min_int_value(1),
+            ),
+    ]
+}
+
+fn mods_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "mods",
+        "Exactly these mods like HDDT, or +HD to include and -EZ to exclude mods",
+    )
+    .set_autocomplete(true)
+}
+
+fn mode_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "mode",
+        "Game mode (defaults to the beatmap's mode)",
+    )
+    .add_string_choice("osu!", "osu")
+    .add_string_choice("osu!taiko", "taiko")
+    .add_string_choice("osu!catch", "fruits")
+    .add_string_choice("osu!mania", "mania")
+}
+
+fn refresh_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        "refresh",
+        "Fetch the latest scores instead of recently cached ones",
+    )
+}
+
+fn lazer_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        "lazer",
+        "Include lazer scores and show lazer scoring",
+    )
+}
+
+fn type_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "type",
+        "global, country, friends or a country code like DE (defaults to country)",
+    )
+}
+
+fn page_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::Integer,
+        "page",
+        "Leaderboard page to show",
+    )
+    .min_int_value(1)
+}
+
+fn player_option() -> CreateCommandOption {
+    CreateCommandOption::new(
+        CommandOptionType::String,
+        "player",
+        "Jump to the page with this player's score",
+    )
+}
+
+pub async fn handle_command(ctx: &Context, command: &CommandInteraction) {
+    if let Err(e) = command.defer(&ctx.http).await {
+        println!("Error deferring interaction: {:?}", e);
+        return;
+    }
+
+    let options = command.data.options();
+
+    let reply = match command.data.name.as_str() {
+        "cs" => match (
+            get_beatmap_option(command, &options),
+            get_leaderboard_options(&options, command.user.id.get()),
+        ) {
+            (Ok(beatmap), Ok(lb_options)) => {
+                commands::handle_beatmap_lb(data_source(), beatmap, &lb_options).await
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        },
+        "ss" => match (
+            get_beatmap_option(command, &options),
+            get_leaderboard_options(&options, command.user.id.get()),
+        ) {
+            (Ok(beatmap), Ok(lb_options)) => {
+                commands::handle_beatmap_server_lb(
+                    data_source(),
+                    &ctx.http,
+                    command.guild_id,
+                    beatmap,
+                    &lb_options,
+                )
+                .await
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        },
+        "rs" => match get_leaderboard_options(&options, command.user.id.get()) {
+            Ok(lb_options) => {
+                let user = get_string_option(&options, "user").unwrap_or("");
+                commands::handle_recent_score(
+                    data_source(),
+                    command.user.id.get(),
+                    user,
+                    &lb_options,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        },
+        "rsc" => match get_leaderboard_options(&options, command.user.id.get()) {
+            Ok(lb_options) => {
+                let user = get_string_option(&options, "user").unwrap_or("");
+                commands::handle_recent_country_lb(
+                    data_source(),
+                    command.user.id.get(),
+                    user,
+                    &lb_options,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        },
+        "compare" => match (
+            get_beatmap_option(command, &options),
+            get_leaderboard_options(&options, command.user.id.get()),
+        ) {
+            (Ok(beatmap), Ok(lb_options)) => {
+                let user = get_string_option(&options, "user").unwrap_or("");
+                commands::handle_compare(
+                    data_source(),
+                    command.user.id.get(),
+                    user,
+                    beatmap,
+                    &lb_options,
+                )
+                .await
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        },
+        "top" => match get_top_options(&options) {
+            Ok(top_options) => {
+                let user = get_string_option(&options, "user").unwrap_or("");
+                commands::handle_top_plays(data_source(), command.user.id.get(), user, &top_options)
+                    .await
+            }
+            Err(e) => Err(e),
+        },
+        "profile" => match commands::parse_mode_arg(get_string_option(&options, "mode")) {
+            Ok(mode) => {
+                let user = get_string_option(&options, "user").unwrap_or("");
+                commands::handle_profile(data_source(), command.user.id.get(), user, mode).await
+            }
+            Err(e) => Err(e),
+        },
+        "map" => match (
+            get_beatmap_option(command, &options),
+            commands::parse_mode_arg(get_string_option(&options, "mode")),
+        ) {
+            (Ok(beatmap), Ok(mode)) => {
+                let mods = get_string_option(&options, "mods").unwrap_or("");
+                commands::handle_beatmap_info(data_source(), beatmap, mods, mode).await
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        },
+        "connect" => match get_int_option(&options, "osu_id") {
+            Some(osu_id) => {
+                commands::handle_connect(command.user.id.get(), &command.user.name, osu_id).await
+            }
+            None => Err("Usage: /connect <osu_id>".to_string()),
+        },
+        _ => Err("Unknown command".to_string()),
+    };
+
+    let response = match reply {
+        Ok(CommandReply {
+            content,
+            attachment,
+            components,
+        }) => {
+            if let Some(beatmap) = find_beatmap_ref(&content) {
+                channel_context::remember_beatmap(command.channel_id.get(), beatmap);
+            }
+
+            let mut response = EditInteractionResponse::new()
+                .content(content)
+                .components(components);
+            if let Some(attachment) = attachment {
+                response = response.new_attachment(attachment);
+            }
+            response
+        }
+        Err(e) => EditInteractionResponse::new().content(e),
+    };
+
+    if let Err(e) = command.edit_response(&ctx.http, response).await {
+        println!("Error sending interaction response: {:?}", e);
+    }
+}
+
+// Page buttons under leaderboards, see commands::page_buttons
+pub async fn handle_component(ctx: &Context, component: &ComponentInteraction) {
+    let custom_id = component.data.custom_id.as_str();
+
+    // The modal keeps the button's custom ID so its submission knows the leaderboard
+    if custom_id.starts_with(commands::FIND_PLAYER_PREFIX) {
+        let modal = CreateModal::new(custom_id, "Find player").components(vec![
+            CreateActionRow::InputText(
+                CreateInputText::new(InputTextStyle::Short, "osu! username or ID", "player")
+                    .required(true),
+            ),
+        ]);
+
+        if let Err(e) = component
+            .create_response(&ctx.http, CreateInteractionResponse::Modal(modal))
+            .await
+        {
+            println!("Error opening player search: {:?}", e);
+        }
+        return;
+    }
+
+    let Some((session_id, page)) = custom_id
+        .strip_prefix(commands::PAGE_BUTTON_PREFIX)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .and_then(|rest| rest.split_once(':'))
+        .and_then(|(id, page)| Some((id.parse::<u64>().ok()?, page.parse::<usize>().ok()?)))
+    else {
+        return;
+    };
+
+    if let Err(e) = component.defer(&ctx.http).await {
+        println!("Error deferring interaction: {:?}", e);
+        return;
+    }
+
+    let reply = commands::handle_leaderboard_page(data_source(), session_id, page).await;
+    match reply {
+        Ok(reply) => {
+            if let Err(e) = component
+                .edit_response(&ctx.http, page_response(reply))
+                .await
+            {
+                println!("Error updating leaderboard page: {:?}", e);
+            }
+        }
+        Err(e) => {
+            let followup = CreateInteractionResponseFollowup::new()
+                .content(e)
+                .ephemeral(true);
+            if let Err(e) = component.create_followup(&ctx.http, followup).await {
+                println!("Error sending interaction followup: {:?}", e);
+            }
+        }
+    }
+}
+
+// Submitted player search from the "Find player" button
+pub async fn handle_modal(ctx: &Context, modal: &ModalInteraction) {
+    let Some(session_id) = modal
+        .data
+        .custom_id
+        .strip_prefix(commands::FIND_PLAYER_PREFIX)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .and_then(|id| id.parse::<u64>().ok())
+    else {
+        return;
+    };
+
+    let player = modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|c| match c {
+            ActionRowComponent::InputText(input) if input.custom_id == "player" => {
+                input.value.clone()
+            }
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    if let Err(e) = modal.defer(&ctx.http).await {
+        println!("Error deferring interaction: {:?}", e);
+        return;
+    }
+
+    match commands::handle_leaderboard_player(data_source(), session_id, &player).await {
+        Ok(reply) => {
+            if let Err(e) = modal.edit_response(&ctx.http, page_response(reply)).await {
+                println!("Error updating leaderboard page: {:?}", e);
+            }
+        }
+        Err(e) => {
+            let followup = CreateInteractionResponseFollowup::new()
+                .content(e)
+                .ephemeral(true);
+            if let Err(e) = modal.create_followup(&ctx.http, followup).await {
+                println!("Error sending interaction followup: {:?}", e);
+            }
+        }
+    }
+}
+
+fn page_response(reply: CommandReply) -> EditInteractionResponse {
+    let mut response = EditInteractionResponse::new()
+        .content(reply.content)
+        .components(reply.components)
+        .clear_attachments();
+    if let Some(attachment) = reply.attachment {
+        response = response.new_attachment(attachment);
+    }
+    response
+}
+
+pub async fn handle_autocomplete(ctx: &Context, interaction: &CommandInteraction) {
+    let Some(focused) = interaction.data.autocomplete() else {
+        return;
+    };
+
+    let mut choices = CreateAutocompleteResponse::new();
+    if focused.name == "mods" {
+        // /map shows stats with the mods, everywhere else they filter scores
+        let suggestions = match interaction.data.name.as_str() {
+            "map" => commands::suggest_mods(focused.value),
+            _ => commands::suggest_mods_filter(focused.value),
+        };
+        for suggestion in suggestions {
+            choices = choices.add_string_choice(suggestion.clone(), suggestion);
+        }
+    }
+
+    if let Err(e) = interaction
+        .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(choices))
+        .await
+    {
+        println!("Error sending autocomplete response: {:?}", e);
+    }
+}
+
+fn get_beatmap_option(
+    command: &CommandInteraction,
+    options: &[ResolvedOption],
+) -> Result<BeatmapRef, String> {
+    match get_string_option(options, "beatmap") {
+        Some(beatmap) => commands::parse_beatmap_arg(beatmap),
+        None => channel_context::get_last_beatmap(command.channel_id.get())
+            .ok_or_else(|| "No beatmap was mentioned in this channel yet".to_string()),
+    }
+}
+
+fn get_leaderboard_options(
+    options: &[ResolvedOption],
+    requester: u64,
+) -> Result<LeaderboardOptions, String> {
+    let mut lb_options = LeaderboardOptions::new(
+        get_string_option(options, "mods").unwrap_or(""),
+        get_string_option(options, "mode"),
+        get_bool_option(options, "lazer").unwrap_or(false),
+        get_string_option(options, "type"),
+    )?;
+
+    if let Some(page) = get_int_option(options, "page") {
+        lb_options.page = page.max(1) as usize;
+    }
+    lb_options.player = get_string_option(options, "player").map(|p| p.to_string());
+    lb_options.requester = Some(requester);
+    lb_options.refresh = get_bool_option(options, "refresh").unwrap_or(false);
+
+    Ok(lb_options)
+}
+
+fn get_top_options(options: &[ResolvedOption]) -> Result<TopPlaysOptions, String> {
+    let mut top_options = TopPlaysOptions::new(
+        get_string_option(options, "mods").unwrap_or(""),
+        get_string_option(options, "mode"),
+        get_string_option(options, "rank"),
+        get_string_option(options, "from"),
+        get_string_option(options, "to"),
+        get_string_option(options, "sort"),
+    )?;
+
+    if let Some(page) = get_int_option(options, "page") {
+        top_options.page = page.max(1) as usize;
+    }
+
+    Ok(top_options)
+}
+
+fn get_string_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> Option<&'a str> {
+    options.iter().find_map(|o| match o.value {
+        ResolvedValue::String(s) if o.name == name => Some(s),
+        _ => None,
+    })
+}
+
+fn get_bool_option(options: &[ResolvedOption], name: &str) -> Option<bool> {
+    options.iter().find_map(|o| match o.value {
+        ResolvedValue::Boolean(b) if o.name == name => Some(b),
+        _ => None,
+    })
+}
+
+fn get_int_option(options: &[ResolvedOption], name: &str) -> Option<i64> {
+    options.iter().find_map(|o| match o.value {
+        ResolvedValue::Integer(i) if o.name == name => Some(i),
+        _ => None,
+    })
+}